@@ -0,0 +1,236 @@
+use rand::{thread_rng, Rng, distributions::{Distribution, WeightedIndex}};
+
+use crate::game;
+use crate::network;
+
+use game::*;
+use network::Network;
+
+// How the policy is updated at the end of an episode
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PolicyUpdate {
+    Reinforce, // REINFORCE with the critic as baseline
+    Ppo // PPO-style clipped updates, multiple epochs over the episode
+}
+
+// A single decision of the policy during an episode
+struct Transition {
+    state: [f64; FEATURE_LENGTH],
+    candidates: Vec<[f64; HEURISTIC_LENGTH]>,
+    action: usize,
+    probability: f64,
+    reward: f64
+}
+
+// Softmax over the candidate placements, the preference of each placement is linear in the heuristic features of the resulting state
+fn softmax(weights: &[f64; HEURISTIC_LENGTH], candidates: &[[f64; HEURISTIC_LENGTH]]) -> Vec<f64> {
+    let preferences : Vec<f64> = candidates.iter().map(|features| {
+        let mut preference = 0.0;
+        for i in 0..HEURISTIC_LENGTH {
+            preference += weights[i] * features[i];
+        }
+        preference
+    }).collect();
+
+    let max_preference = preferences.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let exponentials : Vec<f64> = preferences.iter().map(|preference| (preference - max_preference).exp()).collect();
+    let sum : f64 = exponentials.iter().sum();
+    exponentials.iter().map(|value| value / sum).collect()
+}
+
+// Gradient of the log-probability of an action w.r.t. the policy weights
+fn log_probability_gradient(probabilities: &[f64], candidates: &[[f64; HEURISTIC_LENGTH]], action: usize) -> [f64; HEURISTIC_LENGTH] {
+    let mut gradient = candidates[action];
+    for (j, features) in candidates.iter().enumerate() {
+        for i in 0..HEURISTIC_LENGTH {
+            gradient[i] -= probabilities[j] * features[i];
+        }
+    }
+    gradient
+}
+
+// Actor-Critic Agent: the actor is a softmax policy over the possible placements, the critic estimates the value of the current state
+pub struct ActorCritic {
+    policy_weights: [f64; HEURISTIC_LENGTH],
+    critic: Network,
+    learning_rate: f64,
+    critic_learning_rate: f64,
+    discount_factor: f64,
+    clip_epsilon: f64,
+    ppo_epochs: usize,
+    update: PolicyUpdate,
+    trajectory: Vec<Transition>
+}
+
+impl ActorCritic {
+    pub fn new(update: PolicyUpdate) -> ActorCritic {
+        ActorCritic {
+            policy_weights: [0.0; HEURISTIC_LENGTH],
+            critic: Network::new(),
+            learning_rate: 0.001,
+            critic_learning_rate: 0.00001,
+            discount_factor: 0.9,
+            clip_epsilon: 0.2,
+            ppo_epochs: 4,
+            update,
+            trajectory: Vec::new()
+        }
+    }
+
+    // Sample a placement from the policy and remember the decision for the update at the end of the episode
+    pub fn select_action(&mut self, game: &Game) -> Option<ComposedAction> {
+        let (possible_actions, rewards, sim_features) = game.get_possible_actions();
+        if possible_actions.is_empty() {
+            return None;
+        }
+        let candidates : Vec<[f64; HEURISTIC_LENGTH]> = sim_features.iter().map(|features| features.get_heuristic_vector()).collect();
+        let probabilities = softmax(&self.policy_weights, &candidates);
+        // once training diverges the probabilities can be NaN (on which WeightedIndex panics instead of failing) or all zero,
+        // the placement is then sampled uniformly
+        let distribution = if probabilities.iter().all(|probability| probability.is_finite()) { WeightedIndex::new(&probabilities).ok() } else { None };
+        let (action, probability) = match distribution {
+            Some(distribution) => {
+                let action = distribution.sample(&mut thread_rng());
+                (action, probabilities[action])
+            },
+            None => (thread_rng().gen_range(0, candidates.len()), 1.0 / candidates.len() as f64)
+        };
+
+        self.trajectory.push(Transition {
+            state: Features::build(game.state).get_vector(),
+            candidates,
+            action,
+            probability,
+            reward: rewards[action]
+        });
+        Some(possible_actions[action].clone())
+    }
+
+    // Most probable placement, without recording the decision
+    pub fn greedy_action(&self, game: &Game) -> Option<ComposedAction> {
+        let (possible_actions, _, sim_features) = game.get_possible_actions();
+        let candidates : Vec<[f64; HEURISTIC_LENGTH]> = sim_features.iter().map(|features| features.get_heuristic_vector()).collect();
+        let probabilities = softmax(&self.policy_weights, &candidates);
+        let mut best : Option<(ComposedAction, f64)> = None;
+        for (i, action) in possible_actions.iter().enumerate() {
+            match best {
+                Some((_, probability)) if probability >= probabilities[i] => (),
+                _ => best = Some((action.clone(), probabilities[i]))
+            }
+        }
+        best.map(|(action, _)| action)
+    }
+
+    // Update actor and critic on the recorded episode, returns the mean absolute advantage
+    pub fn end_episode(&mut self) -> f64 {
+        if self.trajectory.is_empty() {
+            return 0.0;
+        }
+
+        // discounted returns
+        let mut returns = vec![0.0; self.trajectory.len()];
+        let mut future_return = 0.0;
+        for (t, transition) in self.trajectory.iter().enumerate().rev() {
+            future_return = transition.reward + self.discount_factor * future_return;
+            returns[t] = future_return;
+        }
+
+        // advantages w.r.t. the critic as baseline, normalized to keep the step size independent of the reward scale
+        let mut advantages = Vec::new();
+        for (t, transition) in self.trajectory.iter().enumerate() {
+            advantages.push(returns[t] - self.critic.forward(transition.state));
+        }
+        let mean_advantage = advantages.iter().map(|advantage| advantage.abs()).sum::<f64>() / advantages.len() as f64;
+        let mean = advantages.iter().sum::<f64>() / advantages.len() as f64;
+        let stddev = (advantages.iter().map(|advantage| (advantage - mean).powi(2)).sum::<f64>() / advantages.len() as f64).sqrt();
+        let normalized : Vec<f64> = advantages.iter().map(|advantage| (advantage - mean) / (stddev + 1e-8)).collect();
+
+        match self.update {
+            PolicyUpdate::Reinforce => self.update_reinforce(&normalized),
+            PolicyUpdate::Ppo => {
+                for _ in 0..self.ppo_epochs {
+                    self.update_ppo(&normalized);
+                }
+            }
+        }
+
+        // critic regresses on the returns, the error is clipped (Huber loss) as large errors make the network diverge
+        for (t, transition) in self.trajectory.iter().enumerate() {
+            let error = (returns[t] - self.critic.forward(transition.state)).clamp(-1.0, 1.0);
+            let gradients = self.critic.backward(1.0, transition.state);
+            self.critic.update_parameters(self.critic_learning_rate * error, gradients.0, gradients.1, gradients.2);
+        }
+
+        self.trajectory.clear();
+        mean_advantage
+    }
+
+    fn update_reinforce(&mut self, advantages: &[f64]) {
+        for (t, transition) in self.trajectory.iter().enumerate() {
+            let probabilities = softmax(&self.policy_weights, &transition.candidates);
+            let gradient = log_probability_gradient(&probabilities, &transition.candidates, transition.action);
+            for (weight, partial) in self.policy_weights.iter_mut().zip(gradient.iter()) {
+                *weight += self.learning_rate * advantages[t] * partial;
+            }
+        }
+    }
+
+    // Clipped surrogate objective: no gradient once the probability ratio leaves [1 - epsilon, 1 + epsilon] in the direction of the advantage
+    fn update_ppo(&mut self, advantages: &[f64]) {
+        for (t, transition) in self.trajectory.iter().enumerate() {
+            let probabilities = softmax(&self.policy_weights, &transition.candidates);
+            let ratio = probabilities[transition.action] / transition.probability;
+            if (advantages[t] > 0.0 && ratio > 1.0 + self.clip_epsilon) || (advantages[t] < 0.0 && ratio < 1.0 - self.clip_epsilon) {
+                continue;
+            }
+            let gradient = log_probability_gradient(&probabilities, &transition.candidates, transition.action);
+            for (weight, partial) in self.policy_weights.iter_mut().zip(gradient.iter()) {
+                *weight += self.learning_rate * advantages[t] * ratio * partial;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two decisions between the same two placements: the first placement earned a reward, the second did not
+    fn episode() -> Vec<Transition> {
+        let mut first = [0.0; HEURISTIC_LENGTH];
+        let mut second = [0.0; HEURISTIC_LENGTH];
+        first[0] = 1.0;
+        second[1] = 1.0;
+        [(0, 10.0), (1, 0.0)].iter().map(|(action, reward)| Transition {
+            state: [0.0; FEATURE_LENGTH],
+            candidates: vec![first, second],
+            action: *action,
+            probability: 0.5,
+            reward: *reward
+        }).collect()
+    }
+
+    #[test]
+    fn update_prefers_rewarded_placement() {
+        for update in [PolicyUpdate::Reinforce, PolicyUpdate::Ppo].iter() {
+            let mut agent = ActorCritic::new(*update);
+            agent.trajectory = episode();
+            let candidates = agent.trajectory[0].candidates.clone();
+            assert!(agent.end_episode() > 0.0);
+            assert!(agent.trajectory.is_empty());
+            let probabilities = softmax(&agent.policy_weights, &candidates);
+            assert!(probabilities[0] > 0.5, "{:?}: {:?}", update, probabilities);
+        }
+    }
+
+    #[test]
+    fn diverged_policy_still_samples() {
+        let mut agent = ActorCritic::new(PolicyUpdate::Reinforce);
+        agent.policy_weights[0] = f64::NAN;
+        let game = Game::with_seed(0);
+        assert!(agent.select_action(&game).is_some());
+        assert_eq!(agent.trajectory.len(), 1);
+        let options = game.get_possible_actions().0.len() as f64;
+        assert!((agent.trajectory[0].probability - 1.0 / options).abs() < 1e-12);
+    }
+}
@@ -30,10 +30,31 @@ impl ReplayBuffer {
 
     fn sample_batch(&self, batch_size: usize) -> Vec<Play> {
         let mut rng = thread_rng();
-        self.buffer.iter().choose_multiple(&mut rng, batch_size).iter().copied().copied().collect()
+        self.buffer.iter().choose_multiple(&mut rng, batch_size).into_iter().cloned().collect()
     }
 }
 
+// index of the action with the highest value
+fn best_action(action_values: &[f64]) -> usize {
+    let mut max_value = action_values[0];
+    let mut idx = 0;
+    for (i, value) in action_values.iter().enumerate().skip(1) {
+        if *value > max_value {
+            max_value = *value;
+            idx = i;
+        }
+    }
+    idx
+}
+
+// Last action selection of the agent: the estimated value of every candidate action and the chosen one
+#[derive(Clone)]
+pub struct Decision {
+    pub action_values: Vec<f64>,
+    pub chosen: usize,
+    pub explored: bool // the action was sampled at random instead of taking the best one
+}
+
 // Agent
 pub struct Agent {
     learning_rate : f64,
@@ -42,7 +63,15 @@ pub struct Agent {
     model: Network,
     target_model: Network,
     replay_buffer: ReplayBuffer,
-    current_iteration: i32
+    current_iteration: i32,
+    last_decision: Option<Decision>,
+    td_error: f64 // mean absolute temporal difference of the last training batch
+}
+
+impl Default for Agent {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Agent {
@@ -53,32 +82,62 @@ impl Agent {
             discount_factor: 0.9,
             exploration_rate: 0.5,
             model: network,
-            target_model: network.clone(),
+            target_model: network,
             replay_buffer: ReplayBuffer::new(),
-            current_iteration: 0
+            current_iteration: 0,
+            last_decision: None,
+            td_error: 0.0
         }
     }
 
-    // Sample the action using epsilon-greedy exploration strategy
-    fn sample_action(&self, action_values: Vec<f64>) -> usize {
+    // Sample the action using epsilon-greedy exploration strategy, returns the action and whether it was explored
+    fn sample_action(&self, action_values: &[f64]) -> (usize, bool) {
         let sample : f64 = rand::thread_rng().gen();
         if sample <= self.exploration_rate { // explore
-            rand::thread_rng().gen_range(0, action_values.len())
+            (rand::thread_rng().gen_range(0, action_values.len()), true)
         } else { // exploit (use best action)
-            let mut max_value = action_values[0];
-            let mut idx = 0 as usize;
-            for i in 1..action_values.len() {
-                if action_values[i] > max_value {
-                    max_value = action_values[i];
-                    idx = i;
-                }
-            }
-            idx
+            (best_action(action_values), false)
         }
     }
 
+    pub fn get_discount_factor(&self) -> f64 {
+        self.discount_factor
+    }
+
+    pub fn get_exploration_rate(&self) -> f64 {
+        self.exploration_rate
+    }
+
+    pub fn get_learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    pub fn get_td_error(&self) -> f64 {
+        self.td_error
+    }
+
+    pub fn get_last_decision(&self) -> Option<&Decision> {
+        self.last_decision.as_ref()
+    }
+
+    // Estimated value of a game state, leaving the weights untouched
+    pub fn state_value(&self, features: &Features) -> f64 {
+        let mut model = self.model; // forward pass stores intermediate results, so use a copy
+        model.forward(features.get_vector())
+    }
+
+    // Sample the best action without exploration, leaving the weights and the replay buffer untouched
+    pub fn greedy_action(&self, rewards: &[f64], sim_features: &[Features]) -> usize {
+        let mut model = self.model; // forward pass stores intermediate results, so use a copy
+        let mut action_values : Vec<f64> = Vec::new();
+        for (i, features) in sim_features.iter().enumerate() {
+            action_values.push(rewards[i] + self.discount_factor * model.forward(features.get_vector()));
+        }
+        best_action(&action_values)
+    }
+
     // LOSS
-    fn compute_error(&mut self, play: Play) -> f64 {
+    fn compute_error(&mut self, play: &Play) -> f64 {
         let mut error = play.reward - self.model.forward(play.previous_state.get_vector());
         if !play.next_state.is_terminal {
             error += self.discount_factor * self.target_model.forward(play.next_state.get_vector());
@@ -93,39 +152,57 @@ impl Agent {
             let qsa = rewards[i] + self.discount_factor * self.model.forward(sim_features[i].get_vector());
             action_values.push(qsa);
         }
-        let action_idx = self.sample_action(action_values);
+        let (action_idx, explored) = self.sample_action(&action_values);
+        self.last_decision = Some(Decision {
+            action_values,
+            chosen: action_idx,
+            explored
+        });
         let play = Play {
             previous_state: features,
             next_state: sim_features[action_idx],
             reward: rewards[action_idx],
-            action: possible_actions[action_idx]
+            action: possible_actions[action_idx].clone()
         };
+        let action = play.action.clone();
         self.replay_buffer.add(play);
 
         // Sample batch of actions/states from replay buffer and use for training
         if self.replay_buffer.count() >= BATCH_SIZE {
             let batch = self.replay_buffer.sample_batch(BATCH_SIZE);
+            let mut total_error = 0.0;
             for sampled_play in batch {
-                let error = self.compute_error(sampled_play);
+                let error = self.compute_error(&sampled_play);
+                total_error += error.abs();
                 let gradients = self.model.backward(error, sampled_play.previous_state.get_vector());
                 self.model.update_parameters(self.learning_rate * error, gradients.0, gradients.1, gradients.2);
             }
+            self.td_error = total_error / BATCH_SIZE as f64;
 
             self.update_hyperparameters();
         }
 
-        play.action
+        action
+    }
+
+    // Move the value of a game state towards an externally computed target, e.g. the value backed up by a tree search
+    // (MctsResult::value for the current board), returns the error before the update
+    pub fn train_value(&mut self, features: Features, target: f64) -> f64 {
+        let error = target - self.model.forward(features.get_vector());
+        let gradients = self.model.backward(error, features.get_vector());
+        self.model.update_parameters(self.learning_rate * error, gradients.0, gradients.1, gradients.2);
+        error
     }
 
     fn update_hyperparameters(&mut self) {
         if self.exploration_rate > 0.01 {
-            self.exploration_rate = self.exploration_rate * 0.99;
+            self.exploration_rate *= 0.99;
         } else {
             self.exploration_rate = 0.01
         }
 
         if self.learning_rate > 0.00001{
-            self.learning_rate = self.learning_rate * 0.999
+            self.learning_rate *= 0.999
         } else {
             self.learning_rate = 0.00001;
         }
@@ -133,7 +210,7 @@ impl Agent {
         // copy network into target network
         self.current_iteration += 1;
         if self.current_iteration % 10 == 0{
-            self.target_model = self.model.clone()
+            self.target_model = self.model
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+use std::fs;
+use std::io;
+
+use crate::input;
+
+use input::Control;
+
+// name of each control in the key binding file
+const CONTROL_NAMES : [(&str, Control); 10] = [
+    ("left", Control::Left),
+    ("right", Control::Right),
+    ("soft_drop", Control::SoftDrop),
+    ("hard_drop", Control::HardDrop),
+    ("rotate_clockwise", Control::RotateClockwise),
+    ("rotate_counter_clockwise", Control::RotateCounterClockwise),
+    ("rotate_180", Control::Rotate180),
+    ("hold", Control::Hold),
+    ("pause", Control::Pause),
+    ("restart", Control::Restart)
+];
+
+const DEFAULT_BINDINGS : [(&str, Control); 13] = [
+    ("Left", Control::Left),
+    ("Right", Control::Right),
+    ("Down", Control::SoftDrop),
+    ("Space", Control::HardDrop),
+    ("Up", Control::RotateClockwise),
+    ("X", Control::RotateClockwise),
+    ("Z", Control::RotateCounterClockwise),
+    ("LCtrl", Control::RotateCounterClockwise),
+    ("A", Control::Rotate180),
+    ("C", Control::Hold),
+    ("LShift", Control::Hold),
+    ("P", Control::Pause),
+    ("R", Control::Restart)
+];
+
+// Keys bound to the controls, keys are identified by their name (e.g. "Left", "Space", "Z", "LShift")
+#[derive(Clone)]
+pub struct KeyBindings {
+    bindings: Vec<(String, Control)>
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyBindings {
+    pub fn new() -> KeyBindings {
+        KeyBindings {
+            bindings: DEFAULT_BINDINGS.iter().map(|(key, control)| (key.to_string(), *control)).collect()
+        }
+    }
+
+    // control bound to the key, key names are not case sensitive
+    pub fn get_control(&self, key: &str) -> Option<Control> {
+        self.bindings.iter()
+            .find(|(bound, _)| bound.eq_ignore_ascii_case(key))
+            .map(|(_, control)| *control)
+    }
+
+    // keys bound to the control
+    pub fn get_keys(&self, control: Control) -> Vec<&str> {
+        self.bindings.iter()
+            .filter(|(_, bound)| *bound == control)
+            .map(|(key, _)| key.as_str())
+            .collect()
+    }
+
+    // Replace the keys of a control, a key can only be bound to a single control
+    pub fn bind(&mut self, control: Control, keys: &[&str]) {
+        self.bindings.retain(|(key, bound)| *bound != control && !keys.iter().any(|new_key| new_key.eq_ignore_ascii_case(key)));
+        for key in keys.iter() {
+            self.bindings.push((key.to_string(), control));
+        }
+    }
+
+    // Read the bindings from a file with lines of the form `control = key, key`, '#' starts a comment.
+    // Controls which are not listed in the file keep their default keys
+    pub fn load(path: &str) -> io::Result<KeyBindings> {
+        let content = fs::read_to_string(path)?;
+        let mut bindings = KeyBindings::new();
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let keys = parts.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("missing '=': {}", line)))?;
+
+            let control = CONTROL_NAMES.iter()
+                .find(|(control_name, _)| control_name.eq_ignore_ascii_case(name))
+                .map(|(_, control)| *control)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unknown control: {}", name)))?;
+            let keys : Vec<&str> = keys.split(',').map(|key| key.trim()).filter(|key| !key.is_empty()).collect();
+            bindings.bind(control, &keys);
+        }
+        Ok(bindings)
+    }
+}
@@ -0,0 +1,169 @@
+use rand::thread_rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::game;
+use crate::linear;
+use crate::evaluation;
+
+use game::HEURISTIC_LENGTH;
+use linear::LinearEvaluator;
+use evaluation::EvaluationConfig;
+
+// Settings of the cross-entropy method
+#[derive(Clone, Copy)]
+pub struct CemConfig {
+    pub population_size: usize,
+    pub elite_fraction: f64,
+    pub games_per_sample: usize,
+    pub max_pieces: i32,
+    pub initial_stddev: f64,
+    pub noise: f64, // added to the variance after each refit to avoid early convergence
+    pub noise_decay: f64, // decrease of the noise per iteration
+    pub first_seed: u64
+}
+
+impl Default for CemConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CemConfig {
+    pub fn new() -> CemConfig {
+        CemConfig {
+            population_size: 50,
+            elite_fraction: 0.2,
+            games_per_sample: 3,
+            max_pieces: 1000,
+            initial_stddev: 10.0,
+            noise: 4.0,
+            noise_decay: 0.1,
+            first_seed: 0
+        }
+    }
+}
+
+// Statistics of a single iteration
+#[derive(Clone, Copy, Debug)]
+pub struct CemIteration {
+    pub iteration: usize,
+    pub mean_fitness: f64,
+    pub elite_fitness: f64,
+    pub best_fitness: f64,
+    pub mean_stddev: f64
+}
+
+// Cross-entropy method: sample weight vectors from a Gaussian, evaluate them on seeded games and refit
+// the Gaussian on the best (elite) samples
+pub struct CrossEntropyMethod {
+    config: CemConfig,
+    mean: [f64; HEURISTIC_LENGTH],
+    stddev: [f64; HEURISTIC_LENGTH],
+    iteration: usize,
+    pub best: LinearEvaluator,
+    pub best_fitness: f64
+}
+
+impl CrossEntropyMethod {
+    pub fn new(config: CemConfig) -> CrossEntropyMethod {
+        CrossEntropyMethod {
+            config,
+            mean: [0.0; HEURISTIC_LENGTH],
+            stddev: [config.initial_stddev; HEURISTIC_LENGTH],
+            iteration: 0,
+            best: LinearEvaluator::new([0.0; HEURISTIC_LENGTH]),
+            best_fitness: f64::NEG_INFINITY
+        }
+    }
+
+    // weights at the mean of the current distribution
+    pub fn mean_weights(&self) -> LinearEvaluator {
+        LinearEvaluator::new(self.mean)
+    }
+
+    // Fitness: average number of cleared lines. All samples of an iteration play the same games
+    fn fitness(&self, evaluator: &LinearEvaluator) -> f64 {
+        let config = EvaluationConfig {
+            num_games: self.config.games_per_sample,
+            first_seed: self.config.first_seed + (self.iteration * self.config.games_per_sample) as u64,
+            max_pieces: self.config.max_pieces
+        };
+        evaluation::evaluate(config, |game| evaluator.select_action(game)).lines.mean
+    }
+
+    fn sample(&self) -> LinearEvaluator {
+        let mut rng = thread_rng();
+        let mut weights = [0.0; HEURISTIC_LENGTH];
+        for (i, weight) in weights.iter_mut().enumerate() {
+            *weight = Normal::new(self.mean[i], self.stddev[i]).unwrap().sample(&mut rng);
+        }
+        LinearEvaluator::new(weights)
+    }
+
+    pub fn iterate(&mut self) -> CemIteration {
+        let mut population : Vec<(LinearEvaluator, f64)> = Vec::new();
+        for _ in 0..self.config.population_size {
+            let evaluator = self.sample();
+            let fitness = self.fitness(&evaluator);
+            population.push((evaluator, fitness));
+        }
+        population.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+        if population[0].1 > self.best_fitness {
+            self.best = population[0].0;
+            self.best_fitness = population[0].1;
+        }
+
+        // refit the distribution on the elite samples
+        let num_elite = ((self.config.population_size as f64 * self.config.elite_fraction).ceil() as usize).max(1);
+        let elite = &population[..num_elite];
+        let noise = (self.config.noise - self.config.noise_decay * self.iteration as f64).max(0.0);
+        for i in 0..HEURISTIC_LENGTH {
+            let mean = elite.iter().map(|(evaluator, _)| evaluator.weights[i]).sum::<f64>() / num_elite as f64;
+            let variance = elite.iter().map(|(evaluator, _)| (evaluator.weights[i] - mean).powi(2)).sum::<f64>() / num_elite as f64;
+            self.mean[i] = mean;
+            self.stddev[i] = (variance + noise).sqrt();
+        }
+
+        let statistics = CemIteration {
+            iteration: self.iteration,
+            mean_fitness: population.iter().map(|(_, fitness)| fitness).sum::<f64>() / population.len() as f64,
+            elite_fitness: elite.iter().map(|(_, fitness)| fitness).sum::<f64>() / num_elite as f64,
+            best_fitness: population[0].1,
+            mean_stddev: self.stddev.iter().sum::<f64>() / HEURISTIC_LENGTH as f64
+        };
+        self.iteration += 1;
+        statistics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refit_adds_noise_to_the_elite_variance() {
+        let mut config = CemConfig::new();
+        config.population_size = 4;
+        config.games_per_sample = 1;
+        config.max_pieces = 20;
+        config.initial_stddev = 0.0;
+        config.noise = 0.25;
+        config.noise_decay = 0.1;
+        let mut cem = CrossEntropyMethod::new(config);
+        cem.mean = [-0.5, -0.2, -3.0, -0.3, -0.2, 1.0, -10.0];
+
+        // without variance all samples are the mean, so only the noise is left after refitting
+        let statistics = cem.iterate();
+        assert_eq!(statistics.iteration, 0);
+        assert_eq!(cem.mean_weights().weights, [-0.5, -0.2, -3.0, -0.3, -0.2, 1.0, -10.0]);
+        assert!(cem.stddev.iter().all(|stddev| (stddev - 0.5).abs() < 1e-12));
+        assert_eq!(statistics.best_fitness, statistics.mean_fitness);
+        assert_eq!(cem.best_fitness, statistics.best_fitness);
+
+        // the noise decays with every iteration
+        cem.stddev = [0.0; HEURISTIC_LENGTH];
+        assert_eq!(cem.iterate().iteration, 1);
+        assert!(cem.stddev.iter().all(|stddev| (stddev - 0.15_f64.sqrt()).abs() < 1e-12));
+    }
+}
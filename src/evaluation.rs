@@ -0,0 +1,204 @@
+use std::fmt;
+
+use crate::game;
+use crate::agent;
+
+use game::*;
+use agent::Agent;
+
+// Statistics of a single quantity over all evaluated games
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64
+}
+
+impl Summary {
+    pub fn from_samples(samples: &[f64]) -> Summary {
+        if samples.is_empty() {
+            return Summary { mean: 0.0, median: 0.0, stddev: 0.0 };
+        }
+        let count = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / count;
+        let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / count;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let middle = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 1 {
+            sorted[middle]
+        } else {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        };
+
+        Summary {
+            mean,
+            median,
+            stddev: variance.sqrt()
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mean {:10.2}  median {:10.2}  stddev {:10.2}", self.mean, self.median, self.stddev)
+    }
+}
+
+// Outcome of a single game
+#[derive(Clone, Copy, Debug)]
+pub struct GameResult {
+    pub lines: i32,
+    pub score: i32,
+    pub pieces: i32,
+    pub length: i32
+}
+
+impl GameResult {
+    pub fn from_game(game: &Game) -> GameResult {
+        GameResult {
+            lines: game.lines_cleared,
+            score: game.score,
+            pieces: game.pieces_placed,
+            length: game.steps
+        }
+    }
+}
+
+// Settings of an evaluation: the games are played with the seeds first_seed, first_seed + 1, ...
+#[derive(Clone, Copy)]
+pub struct EvaluationConfig {
+    pub num_games: usize,
+    pub first_seed: u64,
+    pub max_pieces: i32
+}
+
+impl Default for EvaluationConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvaluationConfig {
+    pub fn new() -> EvaluationConfig {
+        EvaluationConfig {
+            num_games: 20,
+            first_seed: 0,
+            max_pieces: 10000
+        }
+    }
+}
+
+pub struct EvaluationReport {
+    pub results: Vec<GameResult>,
+    pub lines: Summary,
+    pub score: Summary,
+    pub pieces: Summary,
+    pub length: Summary
+}
+
+impl EvaluationReport {
+    pub fn from_results(results: Vec<GameResult>) -> EvaluationReport {
+        let summarize = |value: fn(&GameResult) -> i32| {
+            let samples : Vec<f64> = results.iter().map(|result| value(result) as f64).collect();
+            Summary::from_samples(&samples)
+        };
+        let lines = summarize(|result| result.lines);
+        let score = summarize(|result| result.score);
+        let pieces = summarize(|result| result.pieces);
+        let length = summarize(|result| result.length);
+
+        EvaluationReport {
+            results,
+            lines,
+            score,
+            pieces,
+            length
+        }
+    }
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "games  {}", self.results.len())?;
+        writeln!(f, "lines  {}", self.lines)?;
+        writeln!(f, "score  {}", self.score)?;
+        writeln!(f, "pieces {}", self.pieces)?;
+        write!(f, "length {}", self.length)
+    }
+}
+
+// Play a game without rendering, one placement per piece performed frame by frame with the same gravity and lock delay
+// as in the window, until game over or the piece limit is reached. The game ends early if select_action returns no action
+pub fn play_game<F>(mut game: Game, max_pieces: i32, mut select_action: F) -> GameResult
+    where F: FnMut(&Game) -> Option<ComposedAction> {
+    while game.pieces_placed < max_pieces {
+        let action = match select_action(&game) {
+            Some(action) => action,
+            None => break
+        };
+        if let StepResult::GameOver = game.play(&action) {
+            break;
+        }
+    }
+    GameResult::from_game(&game)
+}
+
+// Play the configured number of seeded games with the given policy
+pub fn evaluate<F>(config: EvaluationConfig, mut select_action: F) -> EvaluationReport
+    where F: FnMut(&Game) -> Option<ComposedAction> {
+    let mut results = Vec::new();
+    for i in 0..config.num_games {
+        let game = Game::with_seed(config.first_seed + i as u64);
+        results.push(play_game(game, config.max_pieces, &mut select_action));
+    }
+    EvaluationReport::from_results(results)
+}
+
+// Evaluate the greedy policy of the agent: no exploration, and neither the weights nor the replay buffer are changed
+pub fn evaluate_agent(agent: &Agent, config: EvaluationConfig) -> EvaluationReport {
+    evaluate(config, |game| {
+        let (possible_actions, rewards, sim_features) = game.get_possible_actions();
+        if possible_actions.is_empty() {
+            return None;
+        }
+        Some(possible_actions[agent.greedy_action(&rewards, &sim_features)].clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear::LinearEvaluator;
+    use crate::timing::GRAVITY_20G;
+
+    #[test]
+    fn headless_games_follow_the_frame_rules() {
+        let evaluator = LinearEvaluator::new([-0.5, -0.2, -3.0, -0.3, -0.2, 1.0, -10.0]);
+        for gravity in [None, Some(0.5), Some(GRAVITY_20G)].iter() {
+            let mut game = Game::with_seed(9);
+            game.timing.gravity_override = *gravity;
+            let headless = play_game(game.search_clone(), 30, |game| evaluator.select_action(game));
+
+            // the same placements one frame at a time, as the AI player in the window performs them
+            let mut inputs = ComposedAction::new();
+            let mut new_piece = true;
+            while game.pieces_placed < 30 {
+                if new_piece {
+                    inputs = evaluator.select_action(&game).unwrap();
+                    new_piece = false;
+                }
+                match game.frame(&mut inputs) {
+                    StepResult::GameOver => break,
+                    StepResult::NewPiece => new_piece = true,
+                    StepResult::Normal => ()
+                }
+            }
+            let framed = GameResult::from_game(&game);
+            assert_eq!((headless.score, headless.lines, headless.pieces, headless.length),
+                       (framed.score, framed.lines, framed.pieces, framed.length), "gravity {:?}", gravity);
+            assert!(game.frames > 0);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+
+use crate::game;
+use crate::search;
+
+use game::*;
+use search::LeafEvaluation;
+
+// Expectimax search: maximise over the placements of the active piece and average over the pieces which
+// can still be drawn from the current bag of the piece generator, once the known next piece has been placed
+pub struct Expectimax<'a> {
+    pub depth: usize, // number of placements to look ahead, the pieces are only known for the first two
+    pub branching: usize, // placements expanded further at each max node, the others are pruned
+    pub evaluation: LeafEvaluation<'a>
+}
+
+// Discounted rewards and cleared lines of the placements leading to a node
+#[derive(Clone, Copy)]
+struct Path {
+    reward: f64,
+    discount: f64,
+    lines: i32
+}
+
+impl<'a> Expectimax<'a> {
+    pub fn new(depth: usize, branching: usize, evaluation: LeafEvaluation<'a>) -> Expectimax<'a> {
+        Expectimax {
+            depth,
+            branching,
+            evaluation
+        }
+    }
+
+    pub fn select_action(&self, game: &Game) -> Option<ComposedAction> {
+        let root = Path { reward: 0.0, discount: 1.0, lines: 0 };
+        let queue = [game.next_piece.get_type()];
+        let mut best : Option<(ComposedAction, f64)> = None;
+        for (action, value) in self.placement_values(game, &queue, &game.get_bag_remaining(), self.depth.max(1), root) {
+            match best {
+                Some((_, best_value)) if best_value >= value => (),
+                _ => best = Some((action, value))
+            }
+        }
+        best.map(|(action, _)| action)
+    }
+
+    // value of the most promising placements of the active piece, `queue` are the known pieces after the active piece
+    // and `remaining` the pieces which can still be drawn from the bag after them
+    fn placement_values(&self, game: &Game, queue: &[u8], remaining: &[u8], depth: usize, path: Path) -> Vec<(ComposedAction, f64)> {
+        let gamma = self.evaluation.discount_factor();
+        let (possible_actions, rewards, sim_features) = game.get_possible_actions();
+
+        let mut candidates : Vec<(usize, Path, f64)> = Vec::new();
+        for i in 0..possible_actions.len() {
+            let next_path = Path {
+                reward: path.reward + path.discount * rewards[i],
+                discount: path.discount * gamma,
+                lines: path.lines + sim_features[i].lines_cleared
+            };
+            let score = self.evaluation.score(next_path.reward, next_path.discount, next_path.lines, &sim_features[i]);
+            candidates.push((i, next_path, score));
+        }
+        if depth == 1 {
+            return candidates.iter().map(|(i, _, score)| (possible_actions[*i].clone(), *score)).collect();
+        }
+
+        candidates.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+        candidates.truncate(self.branching);
+        candidates.iter().map(|(i, next_path, score)| {
+            let value = if sim_features[*i].is_terminal {
+                *score
+            } else {
+                let board = game.simulate_placement(&possible_actions[*i]).unwrap().0;
+                self.ply_value(game, board, queue, remaining, depth - 1, *next_path)
+            };
+            (possible_actions[*i].clone(), value)
+        }).collect()
+    }
+
+    // Value of the best placement of the following piece: the first piece of the queue if it is known, otherwise the
+    // average over the pieces which can still be drawn from the bag
+    fn ply_value(&self, game: &Game, board: Board, queue: &[u8], remaining: &[u8], depth: usize, path: Path) -> f64 {
+        if let Some((active, rest)) = queue.split_first() {
+            let mut hypothetical = game.search_clone();
+            hypothetical.state = board;
+            hypothetical.active_piece = Piece::new_from_idx(*active);
+            // the piece after an unknown piece is only needed to detect a block out, the active piece stands in for it
+            hypothetical.next_piece = Piece::new_from_idx(*rest.first().unwrap_or(active));
+            return self.placement_values(&hypothetical, rest, remaining, depth, path)
+                .into_iter()
+                .map(|(_, value)| value)
+                .fold(f64::NEG_INFINITY, f64::max);
+        }
+
+        // once the bag is empty, a new bag with all pieces is shuffled
+        let bag : Vec<u8> = if remaining.is_empty() { (1..=7).collect() } else { remaining.to_vec() };
+        let mut value = 0.0;
+        for (k, piece) in bag.iter().enumerate() {
+            let mut rest = bag.clone();
+            rest.remove(k);
+            value += self.ply_value(game, board, &[*piece], &rest, depth, path) / bag.len() as f64;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear::LinearEvaluator;
+
+    #[test]
+    fn depth_one_matches_greedy() {
+        let evaluator = LinearEvaluator::new([-0.5, -0.2, -3.0, -0.3, -0.2, 1.0, -10.0]);
+        let search = Expectimax::new(1, 8, LeafEvaluation::Heuristic(evaluator));
+        for seed in 0..3 {
+            let mut game = Game::with_seed(seed);
+            for _ in 0..20 {
+                let greedy = evaluator.select_action(&game).unwrap();
+                assert_eq!(search.select_action(&game), Some(greedy.clone()));
+                if let StepResult::GameOver = game.place(&greedy) {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chance_node_averages_over_the_bag() {
+        let evaluator = LinearEvaluator::new([-0.5, -0.2, -3.0, -0.3, -0.2, 1.0, -10.0]);
+        let search = Expectimax::new(3, 8, LeafEvaluation::Heuristic(evaluator));
+        let gamma = search.evaluation.discount_factor();
+        let game = Game::with_seed(4);
+        let board : Board = "XXXX..XXXX\nXXXXX.XXXX".parse().unwrap();
+        let bag = [2, 5, 7];
+
+        // best placement of every piece which can be drawn, evaluated one by one
+        let mut expected = 0.0;
+        for piece in bag.iter() {
+            let mut hypothetical = game.search_clone();
+            hypothetical.state = board;
+            hypothetical.active_piece = Piece::new_from_idx(*piece);
+            hypothetical.next_piece = hypothetical.active_piece;
+            let (_, rewards, sim_features) = hypothetical.get_possible_actions();
+            let best = rewards.iter().zip(sim_features.iter())
+                .map(|(reward, features)| search.evaluation.score(*reward, gamma, features.lines_cleared, features))
+                .fold(f64::NEG_INFINITY, f64::max);
+            expected += best / bag.len() as f64;
+        }
+        let path = Path { reward: 0.0, discount: 1.0, lines: 0 };
+        let value = search.ply_value(&game, board, &[], &bag, 1, path);
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+
+        // the third placement of a search with the default depth goes through the chance node
+        assert!(search.select_action(&game).is_some());
+    }
+}
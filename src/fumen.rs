@@ -0,0 +1,567 @@
+use std::io;
+
+use crate::game;
+use crate::replay;
+
+use game::*;
+use replay::{Playback, Replay};
+
+// Fumen (version 115), the format used by the community to share Tetris positions, e.g. "v115@vhAAgH".
+// A fumen is a list of pages, each with a field, an optional piece and a comment
+const FUMEN_PREFIX : &str = "v115@";
+const ENCODING_TABLE : &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const FIELD_WIDTH : usize = 10;
+const FIELD_TOP : usize = 23; // rows of the field above the garbage row
+const FIELD_BLOCKS : u32 = ((FIELD_TOP + 1) * FIELD_WIDTH) as u32; // including the garbage row
+const LINE_LENGTH : usize = 47; // the encoded data is split into lines of this length by '?'
+const MAX_REPEAT : u32 = 63; // pages with an unchanged field which can be encoded together
+const MAX_COMMENT_LENGTH : usize = 4095;
+// characters of comments after escaping are encoded from ' ' to '~', four characters at a time
+const COMMENT_CHAR_VALUES : u32 = 96;
+
+// template index of each fumen block value from 1 to 7 (I, L, O, Z, T, J, S), 8 is garbage
+const FUMEN_PIECES : [u8; 7] = [5, 4, 6, 2, 7, 3, 1];
+// blocks of each fumen piece in spawn rotation relative to its center
+const FUMEN_BLOCKS : [[(i32, i32); 4]; 7] = [
+    [(0, 0), (-1, 0), (1, 0), (2, 0)],
+    [(0, 0), (-1, 0), (1, 0), (1, 1)],
+    [(0, 0), (1, 0), (0, 1), (1, 1)],
+    [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    [(0, 0), (-1, 0), (1, 0), (0, 1)],
+    [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+    [(0, 0), (-1, 0), (0, 1), (1, 1)]
+];
+// fumen rotations are 0 reverse, 1 right, 2 spawn and 3 left, this maps them to the rotations of the templates and back
+const ROTATIONS : [i32; 4] = [2, 1, 0, 3];
+
+// Page of a fumen: the field before the piece is placed, the piece and the comment
+#[derive(Clone)]
+pub struct FumenPage {
+    pub board: Board, // the 23 lowest rows of the board are stored
+    pub piece: Option<Piece>,
+    pub comment: String,
+    pub lock: bool // the piece is locked and full rows are cleared for the field of the next page
+}
+
+impl FumenPage {
+    pub fn new(board: Board, piece: Option<Piece>) -> FumenPage {
+        FumenPage {
+            board,
+            piece,
+            comment: String::new(),
+            lock: true
+        }
+    }
+
+    // Game on the field of the page with the pieces of the seed, the piece of the page (or the first piece of the seed)
+    // is the active piece at its spawn position. Fails if the spawn position is blocked
+    pub fn to_game(&self, seed: u64) -> io::Result<Game> {
+        let active_piece = match self.piece {
+            Some(piece) => Piece::new_from_idx(piece.get_type()),
+            None => Game::with_seed(seed).active_piece
+        };
+        Game::from_position(self.board, active_piece, &[], None, seed)
+    }
+}
+
+// Field of the fumen with the garbage row below the board at index 0 and the values of the board cells
+type Field = [[u8; FIELD_WIDTH]; FIELD_TOP + 1];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn field_from_board(board: &Board) -> Field {
+    let mut field = [[0; FIELD_WIDTH]; FIELD_TOP + 1];
+    field[1..].copy_from_slice(&board.state[..FIELD_TOP]);
+    field
+}
+
+fn board_from_field(field: &Field) -> Board {
+    let mut board = Board::new();
+    board.state[..FIELD_TOP].copy_from_slice(&field[1..]);
+    board
+}
+
+// fumen block value of a board cell and back
+fn to_fumen_block(cell: u8) -> u32 {
+    if cell == 0 {
+        0
+    } else if cell == GARBAGE {
+        8
+    } else {
+        FUMEN_PIECES.iter().position(|piece| *piece == cell).map(|idx| idx as u32 + 1).unwrap_or(8)
+    }
+}
+
+fn from_fumen_block(block: u32) -> u8 {
+    match block {
+        0 => 0,
+        1..=7 => FUMEN_PIECES[block as usize - 1],
+        _ => GARBAGE
+    }
+}
+
+// blocks of the fumen piece (1 to 7) with the rotation relative to its center
+fn fumen_blocks(piece: u32, rotation: usize) -> [(i32, i32); 4] {
+    let mut blocks = FUMEN_BLOCKS[piece as usize - 1];
+    for block in blocks.iter_mut() {
+        let (x, y) = *block;
+        *block = match rotation {
+            0 => (-x, -y),
+            1 => (y, -x),
+            3 => (-y, x),
+            _ => (x, y)
+        };
+    }
+    blocks
+}
+
+fn min_corner(cells: &[(i32, i32)]) -> (i32, i32) {
+    (cells.iter().map(|cell| cell.0).min().unwrap_or(0), cells.iter().map(|cell| cell.1).min().unwrap_or(0))
+}
+
+// Fumen stores the position of some pieces relative to a different center, depending on the rotation.
+// Offset from the center of the piece to the stored position
+fn position_offset(piece: u32, rotation: usize) -> (i32, i32) {
+    match (piece, rotation) {
+        (3, 3) => (-1, 1), // O left
+        (3, 0) => (-1, 0), // O reverse
+        (3, 2) => (0, 1), // O spawn
+        (1, 0) => (-1, 0), // I reverse
+        (1, 3) => (0, 1), // I left
+        (7, 2) => (0, 1), // S spawn
+        (7, 1) => (1, 0), // S right
+        (4, 2) => (0, 1), // Z spawn
+        (4, 3) => (-1, 0), // Z left
+        _ => (0, 0)
+    }
+}
+
+// piece of the fumen action with its center at the coordinates of the field (y from the bottom)
+fn decode_piece(piece: u32, rotation: usize, x: i32, y: i32) -> Piece {
+    let offset = position_offset(piece, rotation);
+    let (center_x, center_y) = (x - offset.0, y - offset.1);
+    let blocks : Vec<(i32, i32)> = fumen_blocks(piece, rotation).iter().map(|(i, j)| (center_x + i, center_y + j)).collect();
+    let template = FUMEN_PIECES[piece as usize - 1];
+    let rotation = ROTATIONS[rotation];
+    let origin = Piece::new_at(template, (0, 0), rotation).get_cells();
+    let (blocks_x, blocks_y) = min_corner(&blocks);
+    let (origin_x, origin_y) = min_corner(&origin);
+    Piece::new_at(template, (blocks_x - origin_x, blocks_y - origin_y), rotation)
+}
+
+// fumen piece, rotation and stored position (index in the field from the top left) of the piece
+fn encode_piece(piece: &Piece) -> io::Result<(u32, u32, u32)> {
+    let fumen_piece = to_fumen_block(piece.get_type());
+    let rotation = ROTATIONS[piece.get_rotation() as usize] as usize;
+    let (blocks_x, blocks_y) = min_corner(&fumen_blocks(fumen_piece, rotation));
+    let (cells_x, cells_y) = min_corner(&piece.get_cells());
+    let offset = position_offset(fumen_piece, rotation);
+    let x = cells_x - blocks_x + offset.0;
+    let y = cells_y - blocks_y + offset.1;
+    if x < 0 || x >= FIELD_WIDTH as i32 || y < 0 || y >= FIELD_TOP as i32 {
+        return Err(invalid("piece outside of the field"));
+    }
+    Ok((fumen_piece, rotation as u32, (FIELD_TOP as i32 - y - 1) as u32 * FIELD_WIDTH as u32 + x as u32))
+}
+
+// Lock the piece into the field, clear full rows, and raise the garbage row or mirror the field if requested
+fn lock_piece(field: &mut Field, piece: Option<&Piece>, rise: bool, mirror: bool) {
+    if let Some(piece) = piece {
+        for (x, y) in piece.get_cells().iter() {
+            if *x >= 0 && (*x as usize) < FIELD_WIDTH && *y >= 0 && (*y as usize) < FIELD_TOP {
+                field[*y as usize + 1][*x as usize] = piece.get_type();
+            }
+        }
+    }
+    let mut rows : Vec<[u8; FIELD_WIDTH]> = field[1..].iter().filter(|row| row.contains(&0)).cloned().collect();
+    if rise {
+        rows.insert(0, field[0]);
+        field[0] = [0; FIELD_WIDTH];
+    }
+    rows.resize(FIELD_TOP, [0; FIELD_WIDTH]);
+    for y in 0..FIELD_TOP {
+        field[y + 1] = rows[y];
+        if mirror {
+            field[y + 1].reverse();
+        }
+    }
+}
+
+// Escape the comment like the escape function of JavaScript, which fumen uses
+fn escape(comment: &str, max_length: usize) -> String {
+    let mut escaped = String::new();
+    let mut units = [0; 2];
+    for character in comment.chars() {
+        // escapes and surrogate pairs are never split, so a truncated comment can still be decoded
+        let mut encoded = String::new();
+        for unit in character.encode_utf16(&mut units).iter() {
+            let byte = *unit as u8 as char;
+            if *unit < 128 && (byte.is_ascii_alphanumeric() || "@*_+-./".contains(byte)) {
+                encoded.push(byte);
+            } else if *unit < 256 {
+                encoded += &format!("%{:02X}", unit);
+            } else {
+                encoded += &format!("%u{:04X}", unit);
+            }
+        }
+        if escaped.len() + encoded.len() > max_length {
+            break;
+        }
+        escaped += &encoded;
+    }
+    escaped
+}
+
+fn unescape(escaped: &str) -> String {
+    let characters : Vec<char> = escaped.chars().collect();
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < characters.len() {
+        let hex = |start: usize, length: usize| -> Option<u16> {
+            let digits : String = characters.get(start..start + length)?.iter().collect();
+            u16::from_str_radix(&digits, 16).ok()
+        };
+        if characters[i] == '%' {
+            if characters.get(i + 1) == Some(&'u') {
+                if let Some(unit) = hex(i + 2, 4) {
+                    units.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(unit) = hex(i + 1, 2) {
+                units.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+        let mut buffer = [0; 2];
+        units.extend_from_slice(characters[i].encode_utf16(&mut buffer));
+        i += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+// Values of the base 64 characters, read in order
+struct Reader {
+    values: Vec<u32>,
+    position: usize
+}
+
+impl Reader {
+    // read a number stored in the given number of characters, least significant first
+    fn read(&mut self, length: usize) -> io::Result<u32> {
+        if self.position + length > self.values.len() {
+            return Err(invalid("unexpected end of fumen"));
+        }
+        let mut value = 0;
+        for i in (0..length).rev() {
+            value = value * 64 + self.values[self.position + i];
+        }
+        self.position += length;
+        Ok(value)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position >= self.values.len()
+    }
+}
+
+fn write(values: &mut Vec<u32>, mut value: u32, length: usize) {
+    for _ in 0..length {
+        values.push(value % 64);
+        value /= 64;
+    }
+}
+
+// Decode the pages of a fumen, the data may be preceded by anything ending in "v115@" (e.g. an URL)
+pub fn decode(fumen: &str) -> io::Result<Vec<FumenPage>> {
+    let start = fumen.find(FUMEN_PREFIX).ok_or_else(|| invalid("only fumen version 115 is supported"))?;
+    let mut values = Vec::new();
+    for character in fumen[start + FUMEN_PREFIX.len()..].chars() {
+        if character == '?' || character.is_whitespace() {
+            continue;
+        }
+        let value = ENCODING_TABLE.iter().position(|code| *code as char == character).ok_or_else(|| invalid("invalid fumen character"))?;
+        values.push(value as u32);
+    }
+    let mut reader = Reader {
+        values,
+        position: 0
+    };
+
+    let mut pages = Vec::new();
+    let mut field : Field = [[0; FIELD_WIDTH]; FIELD_TOP + 1];
+    let mut comment = String::new();
+    let mut repeat = 0;
+    while !reader.is_finished() {
+        // changes of the field to the previous one, as runs of blocks with the same change
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            let mut unchanged = false;
+            while index < FIELD_BLOCKS {
+                let run = reader.read(2)?;
+                let change = (run / FIELD_BLOCKS) as i32 - 8;
+                let length = run % FIELD_BLOCKS + 1;
+                unchanged = change == 0 && length == FIELD_BLOCKS;
+                for _ in 0..length {
+                    if index >= FIELD_BLOCKS {
+                        return Err(invalid("field with too many blocks"));
+                    }
+                    let row = FIELD_TOP - (index as usize / FIELD_WIDTH);
+                    let column = index as usize % FIELD_WIDTH;
+                    let block = to_fumen_block(field[row][column]) as i32 + change;
+                    if !(0..=8).contains(&block) {
+                        return Err(invalid("invalid block in field"));
+                    }
+                    field[row][column] = from_fumen_block(block as u32);
+                    index += 1;
+                }
+            }
+            if unchanged {
+                repeat = reader.read(1)?;
+            }
+        }
+
+        let mut action = reader.read(3)?;
+        let piece = action % 8;
+        action /= 8;
+        let rotation = (action % 4) as usize;
+        action /= 4;
+        let position = action % FIELD_BLOCKS;
+        action /= FIELD_BLOCKS;
+        let rise = action % 2 == 1;
+        let mirror = (action / 2) % 2 == 1;
+        let has_comment = (action / 8) % 2 == 1;
+        let lock = (action / 16) % 2 == 0;
+
+        if has_comment {
+            let length = reader.read(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut value = reader.read(5)?;
+                for _ in 0..4 {
+                    escaped.push((b' ' + (value % COMMENT_CHAR_VALUES) as u8) as char);
+                    value /= COMMENT_CHAR_VALUES;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        let piece = if piece == 0 {
+            None
+        } else {
+            let x = (position as usize % FIELD_WIDTH) as i32;
+            let y = FIELD_TOP as i32 - (position as usize / FIELD_WIDTH) as i32 - 1;
+            Some(decode_piece(piece, rotation, x, y))
+        };
+        pages.push(FumenPage {
+            board: board_from_field(&field),
+            piece,
+            comment: comment.clone(),
+            lock
+        });
+        if lock {
+            lock_piece(&mut field, piece.as_ref(), rise, mirror);
+        }
+    }
+    Ok(pages)
+}
+
+// Encode the pages as fumen, the rows of the boards above the fumen field are left out
+pub fn encode(pages: &[FumenPage]) -> io::Result<String> {
+    let mut values = Vec::new();
+    let mut previous : Field = [[0; FIELD_WIDTH]; FIELD_TOP + 1];
+    let mut previous_comment = String::new();
+    let mut repeat_index : Option<usize> = None; // position of the count of pages with an unchanged field
+    for (k, page) in pages.iter().enumerate() {
+        let mut field = field_from_board(&page.board);
+        field[0] = previous[0];
+
+        let mut runs = Vec::new();
+        let mut last : Option<(u32, u32)> = None; // change and length of the current run
+        for index in 0..FIELD_BLOCKS as usize {
+            let row = FIELD_TOP - index / FIELD_WIDTH;
+            let column = index % FIELD_WIDTH;
+            let change = to_fumen_block(field[row][column]) + 8 - to_fumen_block(previous[row][column]);
+            last = match last {
+                Some((last_change, length)) if last_change == change => Some((change, length + 1)),
+                Some((last_change, length)) => {
+                    runs.push((last_change, length));
+                    Some((change, 1))
+                },
+                None => Some((change, 1))
+            };
+        }
+        runs.extend(last);
+        let unchanged = runs.len() == 1 && runs[0].0 == 8;
+        match repeat_index {
+            Some(index) if unchanged && values[index] < MAX_REPEAT => values[index] += 1,
+            _ => {
+                for (change, length) in runs.iter() {
+                    write(&mut values, change * FIELD_BLOCKS + length - 1, 2);
+                }
+                repeat_index = None;
+                if unchanged {
+                    values.push(0);
+                    repeat_index = Some(values.len() - 1);
+                }
+            }
+        }
+
+        let (piece, rotation, position) = match &page.piece {
+            Some(piece) => encode_piece(piece)?,
+            None => (0, 0, 0)
+        };
+        let has_comment = page.comment != previous_comment;
+        let colorize = k == 0;
+        let mut flags = if page.lock { 0 } else { 1 };
+        flags = flags * 2 + has_comment as u32;
+        flags = flags * 2 + colorize as u32;
+        flags *= 2; // mirror
+        flags *= 2; // rise
+        write(&mut values, ((flags * FIELD_BLOCKS + position) * 4 + rotation) * 8 + piece, 3);
+
+        if has_comment {
+            let escaped = escape(&page.comment, MAX_COMMENT_LENGTH).into_bytes();
+            write(&mut values, escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                let mut value = 0;
+                for character in chunk.iter().rev() {
+                    value = value * COMMENT_CHAR_VALUES + (*character - b' ') as u32;
+                }
+                write(&mut values, value, 5);
+            }
+            previous_comment = page.comment.clone();
+        }
+
+        previous = field;
+        if page.lock {
+            lock_piece(&mut previous, page.piece.as_ref(), false, false);
+        }
+    }
+
+    let data : Vec<char> = values.iter().map(|value| ENCODING_TABLE[*value as usize] as char).collect();
+    let lines : Vec<String> = data.chunks(LINE_LENGTH).map(|line| line.iter().collect()).collect();
+    Ok(format!("{}{}", FUMEN_PREFIX, lines.join("?")))
+}
+
+// A page for every piece locked in the replay: the board before the piece was locked and the locked piece
+pub fn pages_from_replay(replay: &Replay) -> io::Result<Vec<FumenPage>> {
+    let mut pages = Vec::new();
+    let mut playback = Playback::new(replay)?;
+    let mut board = playback.game.state;
+    while playback.advance().is_some() {
+        for event in playback.game.get_events().iter() {
+            if let GameEvent::PieceLocked { piece_type, position, rotation } = event {
+                pages.push(FumenPage::new(board, Some(Piece::new_at(*piece_type, *position, *rotation))));
+            }
+        }
+        board = playback.game.state;
+    }
+    pages.push(FumenPage::new(board, None));
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_cells(piece: &Piece) -> [(i32, i32); 4] {
+        let mut cells = piece.get_cells();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn decode_empty_field() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].board.is_empty());
+        assert!(pages[0].piece.is_none());
+        assert!(pages[0].comment.is_empty());
+    }
+
+    #[test]
+    fn t_piece_at_bottom_left() {
+        let piece = Piece::from_name('T').unwrap();
+        let bottom_left = Piece::new_at(piece.get_type(), (0, 1), 0);
+        assert_eq!(encode(&[FumenPage::new(Board::new(), Some(bottom_left))]).unwrap(), "v115@vhA1OJ");
+
+        let pages = decode("v115@vhA1OJ").unwrap();
+        assert_eq!(pages.len(), 1);
+        let decoded = pages[0].piece.unwrap();
+        assert_eq!(decoded.get_type(), piece.get_type());
+        assert_eq!(sorted_cells(&decoded), [(0, 0), (1, 0), (1, 1), (2, 0)]);
+    }
+
+    // includes the rotations of O, I, S and Z which fumen stores relative to a different center (see position_offset)
+    #[test]
+    fn round_trip_all_pieces_and_rotations() {
+        let board = Board::new();
+        let mut pages = Vec::new();
+        for piece_type in 1..=7 {
+            for rotation in 0..4 {
+                for x in -3..FIELD_WIDTH as i32 {
+                    for y in [10, FIELD_TOP as i32 - 1].iter() {
+                        let piece = Piece::new_at(piece_type, (x, *y), rotation);
+                        if !board.is_valid_state(piece) || piece.get_cells().iter().any(|cell| cell.1 >= FIELD_TOP as i32) {
+                            continue;
+                        }
+                        let mut page = FumenPage::new(board, Some(board.hard_drop(piece)));
+                        page.lock = false;
+                        pages.push(page);
+                        let mut page = FumenPage::new(board, Some(piece));
+                        page.lock = false;
+                        pages.push(page);
+                    }
+                }
+            }
+        }
+        let decoded = decode(&encode(&pages).unwrap()).unwrap();
+        assert_eq!(decoded.len(), pages.len());
+        for (page, decoded) in pages.iter().zip(decoded.iter()) {
+            let (piece, decoded) = (page.piece.unwrap(), decoded.piece.unwrap());
+            assert_eq!(decoded.get_type(), piece.get_type());
+            assert_eq!(decoded.get_rotation(), piece.get_rotation(), "rotation of {}", piece.get_name());
+            assert_eq!(sorted_cells(&decoded), sorted_cells(&piece), "cells of {} in rotation {}", piece.get_name(), piece.get_rotation());
+        }
+    }
+
+    #[test]
+    fn round_trip_locked_pages_with_comments() {
+        let board : Board = "XXXX...XXX\nSZJL.OTIXX".parse().unwrap();
+        let piece = board.hard_drop(Piece::new_at(6, (3, 5), 0));
+        let locked = board.simulate_board(piece);
+        let mut first = FumenPage::new(board, Some(piece));
+        first.comment = "O piece, 100% \u{2713} \u{1F3AE}".to_string();
+        let pages = vec![first, FumenPage::new(locked, None)];
+
+        let decoded = decode(&encode(&pages).unwrap()).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].board.state, board.state);
+        assert_eq!(sorted_cells(&decoded[0].piece.unwrap()), sorted_cells(&piece));
+        assert_eq!(decoded[0].comment, pages[0].comment);
+        assert_eq!(decoded[1].board.state, locked.state);
+        assert!(decoded[1].piece.is_none());
+        assert!(decoded[1].comment.is_empty());
+    }
+
+    #[test]
+    fn long_comments_are_cut_between_escapes() {
+        let mut page = FumenPage::new(Board::new(), None);
+        let prefix = "a".repeat(MAX_COMMENT_LENGTH - 2);
+        for (suffix, kept) in [("%", ""), ("\u{2713}", ""), ("\u{1F3AE}", ""), ("bcd", "bc")].iter() {
+            page.comment = format!("{}{}", prefix, suffix);
+            let decoded = decode(&encode(&[page.clone()]).unwrap()).unwrap();
+            assert_eq!(decoded[0].comment, format!("{}{}", prefix, kept), "suffix {}", suffix);
+        }
+        // the escape of the last character fits exactly
+        page.comment = format!("{}%", "a".repeat(MAX_COMMENT_LENGTH - 3));
+        assert_eq!(decode(&encode(&[page.clone()]).unwrap()).unwrap()[0].comment, page.comment);
+    }
+}
@@ -1,4 +1,13 @@
-use rand::{thread_rng, seq::SliceRandom};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use rand::{thread_rng, Rng};
+
+use crate::movegen::{self, Placement, KeyPress, generate_placements};
+use crate::scoring::{Scoring, Spin, detect_spin, SOFT_DROP_POINTS, HARD_DROP_POINTS};
+use crate::timing::{TimingConfig, PieceTimer, GRAVITY_20G, FRAMES_PER_SECOND};
+use crate::replay::{Replay, ReplayInput};
 
 pub const GAME_WIDTH : usize = 10;
 pub const GAME_HEIGHT : usize = 24;
@@ -48,36 +57,85 @@ const YELLOW : [f32; 4] = [1.0, 1.0, 0.0, 1.0];
 const CYAN : [f32; 4] = [0.0, 1.0, 1.0, 1.0];
 const ORANGE : [f32; 4] = [1.0, 0.64, 0.0, 1.0];
 const PURPLE : [f32; 4] = [0.54, 0.16, 0.88, 1.0];
+const GRAY : [f32; 4] = [0.5, 0.5, 0.5, 1.0];
 pub const PIECE_COLORS : [[f32; 4]; 7] = [GREEN, RED, BLUE, ORANGE, CYAN, YELLOW, PURPLE];
+// value of board cells which don't belong to a piece, e.g. garbage rows of imported positions
+pub const GARBAGE : u8 = 8;
+// letter of each piece, in the order of the templates
+pub const PIECE_NAMES : [&str; 7] = ["S", "Z", "J", "L", "I", "O", "T"];
 
 pub const FEATURE_LENGTH : usize = 4; //GAME_WIDTH * 2 + 2;
+pub const HEURISTIC_LENGTH : usize = 7;
 
-// Piece Generator: all 7 pieces are shuffled into a random order instead of simple picking a new piece every time by random
+// Random number generator of the piece sequence (SplitMix64). It is part of the crate instead of a generator of the rand
+// crate, whose output may change between versions and platforms, so seeded games and replays stay reproducible
 #[derive(Clone, Copy)]
+struct SeededRng {
+    state : u64
+}
+
+impl SeededRng {
+    fn new(seed: u64) -> SeededRng {
+        SeededRng {
+            state: seed
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // number in [0, n), the bias of the multiplication is negligible for small n
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    // Fisher-Yates shuffle
+    fn shuffle(&mut self, values: &mut [u8]) {
+        for i in (1..values.len()).rev() {
+            let j = self.below(i + 1);
+            values.swap(i, j);
+        }
+    }
+}
+
+// Piece Generator: all 7 pieces are shuffled into a random order instead of simple picking a new piece every time by random
+// The generator owns its random number generator, so a game started from the same seed always sees the same pieces
+#[derive(Clone)]
 struct PieceGenerator {
     bag : [u8; 7],
-    idx : usize
+    idx : usize,
+    rng : SeededRng,
+    queue : Vec<u8> // pieces given in advance (see Game::from_position), drawn before the bag
 }
 
 impl PieceGenerator {
-    fn new() -> PieceGenerator {
-        let mut rng = thread_rng();
+    fn new(seed: u64) -> PieceGenerator {
+        let mut rng = SeededRng::new(seed);
         let mut bag = [1, 2, 3, 4, 5, 6, 7];
-        bag.shuffle(&mut rng);
+        rng.shuffle(&mut bag);
 
         PieceGenerator {
             bag,
-            idx: 0
+            idx: 0,
+            rng,
+            queue: Vec::new()
         }
     }
 
     fn get_next(&mut self) -> Piece {
-        let mut rng = thread_rng();
+        if !self.queue.is_empty() {
+            return Piece::new_from_idx(self.queue.remove(0));
+        }
         let piece = Piece::new_from_idx(self.bag[self.idx]);
         self.idx += 1;
         if self.idx > 6 {
             self.idx = 0;
-            self.bag.shuffle(&mut rng);
+            self.rng.shuffle(&mut self.bag);
         }
         piece
     }
@@ -89,23 +147,76 @@ pub struct Piece {
     pub shape: [[[u8; 4]; 4]; 4],
     position: (i32, i32),
     rotation: i32,
-    num_rotations: i32,
     pub color: [f32; 4]
 }
 
 impl Piece {
-    fn new_from_idx(piece: u8) -> Piece {
+    // piece by its index in the templates, starting at 1
+    pub fn new_from_idx(piece: u8) -> Piece {
         Piece {
             shape: PIECE_TEMPLATES[(piece - 1) as usize],
             position: (3, GAME_HEIGHT as i32 - 1),
             rotation: 0,
-            num_rotations: if piece == 6 {1} else {4},
             color: PIECE_COLORS[(piece - 1) as usize]
         }
     }
 
-    fn simulate_next(&self, action: SingularAction) -> Piece {
-        let mut piece = self.clone();
+    // piece by its index in the templates with the top left corner of its shape at the position
+    pub fn new_at(piece: u8, position: (i32, i32), rotation: i32) -> Piece {
+        let mut new_piece = Piece::new_from_idx(piece);
+        new_piece.position = position;
+        new_piece.rotation = rotation.rem_euclid(4);
+        new_piece
+    }
+
+    // piece of the letter (see PIECE_NAMES) at its spawn position, not case sensitive
+    pub fn from_name(name: char) -> Option<Piece> {
+        PIECE_NAMES.iter()
+            .position(|piece_name| piece_name.eq_ignore_ascii_case(&name.to_string()))
+            .map(|idx| Piece::new_from_idx(idx as u8 + 1))
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        PIECE_NAMES[self.get_type() as usize - 1]
+    }
+
+    pub fn get_position(&self) -> (i32, i32) {
+        self.position
+    }
+
+    pub fn get_rotation(&self) -> i32 {
+        self.rotation
+    }
+
+    // index of the piece in the templates, starting at 1
+    pub fn get_type(&self) -> u8 {
+        for row in self.shape[0].iter() {
+            for value in row.iter() {
+                if *value != 0 {
+                    return *value;
+                }
+            }
+        }
+        0
+    }
+
+    // board coordinates (x, y) of the four blocks of the piece
+    pub fn get_cells(&self) -> [(i32, i32); 4] {
+        let mut cells = [(0, 0); 4];
+        let mut count = 0;
+        for (j, row) in self.shape[self.rotation as usize].iter().enumerate() {
+            for (i, value) in row.iter().enumerate() {
+                if *value != 0 && count < 4 {
+                    cells[count] = (self.position.0 + i as i32, self.position.1 - j as i32);
+                    count += 1;
+                }
+            }
+        }
+        cells
+    }
+
+    pub fn simulate_next(&self, action: SingularAction) -> Piece {
+        let mut piece = *self;
         match action {
             SingularAction::Left => { 
                 piece.position.0 -= 1;
@@ -113,53 +224,97 @@ impl Piece {
             SingularAction::Right => {
                 piece.position.0 += 1;
             },
-            SingularAction::Down => {
+            SingularAction::Down | SingularAction::SoftDrop => {
                 piece.position.1 -= 1;
             },
             SingularAction::Rotate => {
                 piece.rotation = (piece.rotation + 1) % 4;
+            },
+            SingularAction::RotateCounterClockwise => {
+                piece.rotation = (piece.rotation + 3) % 4;
+            },
+            SingularAction::Rotate180 => {
+                piece.rotation = (piece.rotation + 2) % 4;
             }
             _ => ()
         }
         piece
     }
+}
 
-    fn simulate_action(&self, action: ComposedAction) -> Piece {
-        let mut piece = self.clone();
-        piece.position.0 += action.shift;
-        piece.rotation = action.rotation;
+// colour of a board cell which is not empty
+pub fn get_cell_color(cell: u8) -> [f32; 4] {
+    if cell == GARBAGE { GRAY } else { PIECE_COLORS[(cell - 1) as usize] }
+}
 
-        piece
+// Board
+#[derive(Clone, Copy)]
+pub struct Board {
+    pub state: [[u8; GAME_WIDTH]; GAME_HEIGHT]
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn get_aabb(&self, rotation: usize) -> [(i32, i32);2] {
-        let shape = self.shape[rotation];
-        let mut minmax = [(-1 as i32, -1 as i32); 2];
-        for (j, row) in shape.iter().enumerate() {
-            for (i, value) in row.iter().enumerate() {
-                if *value != 0 {
-                    if minmax[0].1 == -1 {
-                        minmax[0].1 = j as i32;
-                    }
-                    minmax[1].1 = j as i32;
+// character of a cell in the text format of the board
+fn cell_name(cell: u8) -> char {
+    match cell {
+        0 => '.',
+        GARBAGE => 'X',
+        _ => PIECE_NAMES[cell as usize - 1].chars().next().unwrap_or('?')
+    }
+}
 
-                    if minmax[0].0 == -1 {
-                        minmax[0].0 = i as i32;
-                    }
-                    minmax[1].0 = i as i32;
-                }
+// Text format of the board: the rows from top to bottom separated by new lines (or '/'), each row lists the cells
+// from left to right as '.' for empty cells, the letter of the piece they belong to (see PIECE_NAMES) or 'X' (or 'G')
+// for garbage. The last row is the bottom of the board and rows shorter than the board are empty on the right, e.g.
+//   ....T.....
+//   XXX.TT.XXX
+impl FromStr for Board {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> Result<Board, io::Error> {
+        let rows : Vec<&str> = text.split(['/', '\n']).map(|row| row.trim()).filter(|row| !row.is_empty()).collect();
+        if rows.len() > GAME_HEIGHT {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("more than {} rows", GAME_HEIGHT)));
+        }
+        let mut board = Board::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            if row.chars().count() > GAME_WIDTH {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("row longer than {} cells: {}", GAME_WIDTH, row)));
+            }
+            for (x, cell) in row.chars().enumerate() {
+                board.state[y][x] = match cell.to_ascii_uppercase() {
+                    '.' => 0,
+                    'X' | 'G' => GARBAGE,
+                    _ => Piece::from_name(cell).map(|piece| piece.get_type())
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unknown cell: {}", cell)))?
+                };
             }
         }
-
-        [(minmax[0].0, minmax[0].1), (minmax[1].0 - minmax[0].0, minmax[1].1 - minmax[0].1)]
+        Ok(board)
     }
 }
 
-// Board
-#[derive(Clone, Copy)]
-pub struct Board {
-    pub state: [[u8; GAME_WIDTH]; GAME_HEIGHT]
+// the rows from the highest row which is not empty down to the bottom of the board, in the text format of Board::from_str
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let top = (0..GAME_HEIGHT).rev().find(|y| self.state[*y].iter().any(|cell| *cell != 0)).unwrap_or(0);
+        for y in (0..=top).rev() {
+            let row : String = self.state[y].iter().map(|cell| cell_name(*cell)).collect();
+            if y > 0 {
+                writeln!(f, "{}", row)?;
+            } else {
+                write!(f, "{}", row)?;
+            }
+        }
+        Ok(())
+    }
 }
+
 fn is_row_full(row: &[u8; GAME_WIDTH]) -> bool {
     for value in row.iter() {
         if *value == 0 {
@@ -170,9 +325,14 @@ fn is_row_full(row: &[u8; GAME_WIDTH]) -> bool {
 }
 
 impl Board {
+    // indices of the full rows, from bottom to top
+    pub fn get_full_rows(&self) -> Vec<usize> {
+        (0..GAME_HEIGHT).filter(|j| is_row_full(&self.state[*j])).collect()
+    }
+
     fn remove_full_rows(&self) -> (Board, i32) {
         let mut removed_rows = 0;
-        let mut state = self.state.clone();
+        let mut state = self.state;
         for (j, row) in self.state.iter().enumerate().rev() {
             if is_row_full(row) {
                 for _j in j..GAME_HEIGHT - 1 {
@@ -187,6 +347,27 @@ impl Board {
         }, removed_rows)
     }
 
+    pub fn new() -> Board {
+        Board {
+            state: [[0; GAME_WIDTH]; GAME_HEIGHT]
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.iter().all(|row| row.iter().all(|value| *value == 0))
+    }
+
+    // the piece at the lowest position it can be moved down to from its current position, where a hard drop locks it
+    pub fn hard_drop(&self, piece: Piece) -> Piece {
+        let mut dropped = piece;
+        let mut below = dropped.simulate_next(SingularAction::SoftDrop);
+        while self.is_valid_state(below) {
+            dropped = below;
+            below = below.simulate_next(SingularAction::SoftDrop);
+        }
+        dropped
+    }
+
     pub fn simulate_board(&self, piece: Piece) -> Board {
         self.integrate_piece(piece)
     }
@@ -195,7 +376,7 @@ impl Board {
         self.fill_rect(piece.position, piece.shape[piece.rotation as usize])
     }
 
-    fn is_valid_state(&self, piece: Piece) -> bool {
+    pub fn is_valid_state(&self, piece: Piece) -> bool {
         let offset_x = piece.position.0;
         let offset_y = piece.position.1;
         for (j, row) in piece.shape[piece.rotation as usize].iter().enumerate() {
@@ -220,7 +401,7 @@ impl Board {
     }
 
     fn fill_rect(&self, offset: (i32, i32), content: [[u8; 4]; 4]) -> Board {
-        let mut state = self.state.clone();
+        let mut state = self.state;
         for (j, row) in content.iter().enumerate() {
             for (i, value) in row.iter().enumerate() {
                 if *value != 0 {
@@ -234,65 +415,78 @@ impl Board {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum SingularAction {
-    None,
+    None, // gravity: the piece moves down a single row and is locked if it can't
     Left,
     Right,
-    Rotate,
-    Down
+    Rotate, // clockwise
+    RotateCounterClockwise,
+    Rotate180,
+    SoftDrop, // the piece moves down a single row, but is never locked
+    Down, // hard drop
+    Hold
 }
 
 // Action of multiple Singular Actions performed in sequence (over multiple time steps)
-#[derive(Clone, Copy)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct ComposedAction {
-    pub rotation : i32,
-    pub shift: i32
+    pub inputs: Vec<SingularAction>
+}
+
+impl Default for ComposedAction {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ComposedAction {
     pub fn new() -> ComposedAction {
         ComposedAction {
-            rotation: 0,
-            shift: 0
+            inputs: Vec::new()
+        }
+    }
+
+    pub fn from_inputs(inputs: Vec<SingularAction>) -> ComposedAction {
+        ComposedAction {
+            inputs
         }
     }
 
+    // rotate clockwise, shift to the left (negative) or right (positive) and hard drop
+    pub fn from_rotation_and_shift(rotation: i32, shift: i32) -> ComposedAction {
+        let mut inputs = vec![SingularAction::Rotate; rotation as usize];
+        let direction = if shift < 0 { SingularAction::Left } else { SingularAction::Right };
+        inputs.extend(vec![direction; shift.unsigned_abs() as usize]);
+        inputs.push(SingularAction::Down);
+        ComposedAction::from_inputs(inputs)
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.rotation == 0 && self.shift == 0
+        self.inputs.is_empty()
     }
 
     // Return the next singular action and the remaining actions
     pub fn decompose(&self) -> (SingularAction, ComposedAction) {
-        let mut action = SingularAction::None;
-        let mut remaining_action = self.clone();
-
-        if remaining_action.rotation != 0 {
-            action = SingularAction::Rotate;
-            remaining_action.rotation -= 1;
-        } else {
-            if remaining_action.shift < 0 {
-                action = SingularAction::Left;
-                remaining_action.shift += 1;
-            } else if remaining_action.shift > 0 {
-                action = SingularAction::Right;
-                remaining_action.shift -= 1;
-            }
+        match self.inputs.split_first() {
+            Some((action, remaining)) => (*action, ComposedAction::from_inputs(remaining.to_vec())),
+            None => (SingularAction::None, ComposedAction::new())
         }
-        (action, remaining_action)
     }
 }
 
 #[derive(Clone, Copy)]
 pub struct Features {
-    heights: [i32; GAME_WIDTH],
-    height_differences: [i32; GAME_WIDTH - 1],
-    maximum_height: i32,
-    average_height: f64,
-    number_of_holes: [i32; GAME_WIDTH],
-    total_no_holes: i32,
+    pub heights: [i32; GAME_WIDTH],
+    pub height_differences: [i32; GAME_WIDTH - 1],
+    pub maximum_height: i32,
+    pub average_height: f64,
+    pub number_of_holes: [i32; GAME_WIDTH],
+    pub total_no_holes: i32,
     pub is_terminal: bool,
-    sum_of_height_differences: i32
+    pub sum_of_height_differences: i32,
+    pub sum_of_well_depths: i32,
+    pub lines_cleared: i32 // lines cleared by the action leading to this state
 }
 
 impl Features {
@@ -305,6 +499,7 @@ impl Features {
         let mut number_of_holes = [0; GAME_WIDTH];
         let mut total_no_holes = 0;
         let mut sum_of_height_differences = 0;
+        let mut sum_of_well_depths = 0;
 
         for i in 0..GAME_WIDTH {
             for j in 0..GAME_HEIGHT {
@@ -333,7 +528,17 @@ impl Features {
             }
         }
 
-        average_height = average_height / GAME_WIDTH as f64;
+        average_height /= GAME_WIDTH as f64;
+
+        // wells: columns lower than both neighbours, the walls count as full columns
+        for i in 0..GAME_WIDTH {
+            let left = if i > 0 { heights[i - 1] } else { GAME_HEIGHT as i32 };
+            let right = if i < GAME_WIDTH - 1 { heights[i + 1] } else { GAME_HEIGHT as i32 };
+            let depth = left.min(right) - heights[i];
+            if depth > 0 {
+                sum_of_well_depths += depth;
+            }
+        }
 
         Features {
             heights, 
@@ -343,7 +548,9 @@ impl Features {
             number_of_holes,
             total_no_holes,
             is_terminal: false,
-            sum_of_height_differences
+            sum_of_height_differences,
+            sum_of_well_depths,
+            lines_cleared: 0
         }
     }
 
@@ -369,6 +576,19 @@ impl Features {
         feature_vector
 
     }
+
+    // feature vector for a linear evaluation of the game state
+    pub fn get_heuristic_vector(&self) -> [f64; HEURISTIC_LENGTH] {
+        [
+            self.average_height,
+            self.maximum_height as f64,
+            self.total_no_holes as f64,
+            self.sum_of_height_differences as f64,
+            self.sum_of_well_depths as f64,
+            self.lines_cleared as f64,
+            if self.is_terminal { 1.0 } else { 0.0 }
+        ]
+    }
 }
 
 pub enum StepResult {
@@ -377,35 +597,144 @@ pub enum StepResult {
     GameOver
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TopOutReason {
+    BlockOut, // the new active piece overlaps the blocks on the board
+    LockOut // a piece has been locked entirely above the visible part of the board
+}
+
+// Events of a single step, in the order in which they happened
+#[derive(Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    PieceLocked { piece_type: u8, position: (i32, i32), rotation: i32 },
+    LinesCleared { rows: Vec<usize> },
+    Spin(Spin),
+    Combo(i32), // number of consecutive line clears before this one
+    BackToBack,
+    PerfectClear,
+    ScoreChanged { delta: i32, score: i32 },
+    LevelUp(i32),
+    HoldUsed { held: u8, active: u8 }, // piece types of the held and the new active piece
+    TopOut(TopOutReason)
+}
+
+// the piece is locked entirely above the visible part of the board
+fn is_lock_out(piece: &Piece) -> bool {
+    piece.get_cells().iter().all(|cell| cell.1 >= RENDER_HEIGHT as i32)
+}
+
+// Position a game was started from with Game::from_position, stored in its replay to simulate the game again
+#[derive(Clone)]
+pub struct StartPosition {
+    pub board: Board,
+    pub active_piece: Piece,
+    pub queue: Vec<Piece>,
+    pub hold_piece: Option<Piece>
+}
+
 // Game
+#[derive(Clone)]
 pub struct Game {
     pub state: Board,
 
     pub active_piece : Piece,
     pub next_piece : Piece,
+    pub hold_piece : Option<Piece>,
+    pub hold_used : bool, // the piece can only be held once until it is locked
     pub score : i32,
+    pub scoring : Scoring, // combo and back-to-back state
+    events : Vec<GameEvent>, // events of the last step
+    rotated_last : bool, // the last successful move of the active piece was a rotation
+    pub timing : TimingConfig,
+    timer : PieceTimer,
+
+    // statistics of the current game
+    pub lines_cleared : i32,
+    pub pieces_placed : i32,
+    pub piece_counts : [i32; 7], // locked pieces by type
+    pub steps : i32,
+    pub frames : i32, // frames advanced by Game::tick
+
+    seed : u64,
+    generator : PieceGenerator,
+    start_position : Option<StartPosition>, // None for games started from an empty board
+    recording : Option<Replay> // inputs since Game::start_recording
+}
 
-    generator : PieceGenerator
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Game {
-        let mut generator = PieceGenerator::new();
+        Game::with_seed(thread_rng().gen())
+    }
+
+    // Game whose piece sequence is fully determined by the seed
+    pub fn with_seed(seed: u64) -> Game {
+        let mut generator = PieceGenerator::new(seed);
         let active_piece = generator.get_next();
+        Game::start(seed, Board::new(), active_piece, generator)
+    }
+
+    // the next piece is drawn from the generator
+    fn start(seed: u64, state: Board, active_piece: Piece, mut generator: PieceGenerator) -> Game {
         let next_piece = generator.get_next();
+        let timer = PieceTimer::new(active_piece.position.1);
         Game {
-            state: Board {
-                state: [[0; GAME_WIDTH]; GAME_HEIGHT]
-            },
+            state,
             active_piece,
             next_piece,
+            hold_piece: None,
+            hold_used: false,
             score: 0,
-            generator
+            scoring: Scoring::new(),
+            events: Vec::new(),
+            rotated_last: false,
+            timing: TimingConfig::new(),
+            timer,
+            lines_cleared: 0,
+            pieces_placed: 0,
+            piece_counts: [0; 7],
+            steps: 0,
+            frames: 0,
+            seed,
+            generator,
+            start_position: None,
+            recording: None
+        }
+    }
+
+    // Game starting from an arbitrary position, e.g. for puzzles or to reproduce a bug: the board, the active piece
+    // (at the given position), the queue of next pieces and the held piece. The pieces after the queue are drawn
+    // from the bags of the seed. Fails if the active piece overlaps the board
+    pub fn from_position(board: Board, active_piece: Piece, queue: &[Piece], hold_piece: Option<Piece>, seed: u64) -> io::Result<Game> {
+        if !board.is_valid_state(active_piece) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the active piece overlaps the board"));
         }
+        let mut generator = PieceGenerator::new(seed);
+        generator.queue = queue.iter().map(|piece| piece.get_type()).collect();
+        let mut game = Game::start(seed, board, active_piece, generator);
+        game.hold_piece = hold_piece;
+        game.start_position = Some(StartPosition {
+            board,
+            active_piece,
+            queue: queue.to_vec(),
+            hold_piece
+        });
+        Ok(game)
+    }
+
+    // pieces placed per second of game time
+    pub fn get_pieces_per_second(&self) -> f64 {
+        if self.frames == 0 { 0.0 } else { self.pieces_placed as f64 * FRAMES_PER_SECOND / self.frames as f64 }
     }
 
+    // the level increases every 10 cleared lines
     pub fn get_level(&self) -> i32 {
-        self.score / 10 + 1
+        self.lines_cleared / 10 + 1
     }
 
     pub fn get_possible_actions(&self) -> (Vec<ComposedAction>, Vec<f64>, Vec<Features>) {
@@ -415,111 +744,502 @@ impl Game {
         let mut rewards : Vec<f64> = Vec::new();
         let mut v_sim_features : Vec<Features> = Vec::new();
 
-        for rotation in 0..self.active_piece.num_rotations {
-            let aabb = self.active_piece.get_aabb(rotation as usize);
-            for shift in (-self.active_piece.position.0 - aabb[0].0)..(GAME_WIDTH as i32 - (self.active_piece.position.0 + aabb[1].0)) {
-                let action = ComposedAction{ rotation, shift: shift as i32};
-                match self.simulate_action(action, features.average_height) {
-                    Some((reward, sim_features)) => {
-                        rewards.push(reward);
-                        v_sim_features.push(sim_features);
-                        possible_actions.push(action);
-                    },
-                    None => continue
-                }
-            }
+        for placement in self.get_reachable_placements() {
+            let (reward, sim_features) = self.simulate_locked_piece(placement.piece, features.average_height);
+            rewards.push(reward);
+            v_sim_features.push(sim_features);
+            possible_actions.push(ComposedAction::from_inputs(placement.path));
         }
         (possible_actions, rewards, v_sim_features)
     }
 
     // return a game state after the action has been taken
-    pub fn simulate_action(&self, action: ComposedAction, avg_height: f64) -> Option<(f64, Features)> {
-        let mut piece = self.active_piece.simulate_action(action);
-        if !self.state.is_valid_state(piece) {
-            return None;
-        }
+    pub fn simulate_action(&self, action: &ComposedAction, avg_height: f64) -> Option<(f64, Features)> {
+        let piece = self.simulate_inputs(&action.inputs)?;
+        Some(self.simulate_locked_piece(piece, avg_height))
+    }
 
-        let mut piece_next = piece.simulate_next(SingularAction::Down);
-        while self.state.is_valid_state(piece_next) {
-            piece = piece_next.clone();
-            piece_next = piece_next.simulate_next(SingularAction::Down);
-        }
-        let mut simulated_board = self.state.integrate_piece(piece);
-        let board_update = simulated_board.remove_full_rows();
-        simulated_board = board_update.0;
+    // return the reward and the game state after the piece has been locked at its current position
+    pub fn simulate_locked_piece(&self, piece: Piece, avg_height: f64) -> (f64, Features) {
+        let board_update = self.state.integrate_piece(piece).remove_full_rows();
+        let simulated_board = board_update.0;
         let mut reward = board_update.1 as f64;
         let mut features = Features::build(simulated_board);
+        features.lines_cleared = board_update.1;
         reward = reward + avg_height - features.average_height;
-        if !simulated_board.is_valid_state(self.next_piece){
+        if is_lock_out(&piece) || !simulated_board.is_valid_state(self.next_piece){
             features.is_terminal = true;
             reward = -5.0;
         }
-        Some((reward, features))
+        (reward, features)
+    }
+
+    // return the board after the action has been taken and the piece dropped, and the number of removed rows
+    pub fn simulate_placement(&self, action: &ComposedAction) -> Option<(Board, i32)> {
+        let piece = self.simulate_inputs(&action.inputs)?;
+        Some(self.state.integrate_piece(piece).remove_full_rows())
+    }
+
+    // return the piece at the position where it is locked after performing the single actions and a hard drop
+    pub fn simulate_inputs(&self, inputs: &[SingularAction]) -> Option<Piece> {
+        let mut piece = self.active_piece;
+        if !self.state.is_valid_state(piece) {
+            return None;
+        }
+
+        for action in inputs.iter() {
+            match action {
+                SingularAction::Down => break,
+                SingularAction::Hold => {
+                    if !self.hold_used {
+                        piece = self.hold_piece.unwrap_or(self.next_piece);
+                    }
+                },
+                _ => {
+                    let next = piece.simulate_next(*action);
+                    if self.state.is_valid_state(next) {
+                        piece = next;
+                    } else if *action == SingularAction::None {
+                        return Some(piece);
+                    }
+                }
+            }
+        }
+        Some(self.state.hard_drop(piece))
+    }
+
+    // the active piece at the position where a hard drop would lock it
+    pub fn get_ghost_piece(&self) -> Piece {
+        self.simulate_inputs(&[]).unwrap_or(self.active_piece)
+    }
+
+    // pieces (by index) which are left in the current bag and will be drawn after the next piece, in no particular order
+    pub fn get_bag_remaining(&self) -> Vec<u8> {
+        if let Some(piece) = self.generator.queue.first() {
+            return vec![*piece]; // the piece after the next one is known
+        }
+        let mut remaining = self.generator.bag[self.generator.idx..].to_vec();
+        remaining.sort_unstable();
+        remaining
+    }
+
+    // every placement of the active piece which can be reached with single actions, including tucks, slides and spins
+    pub fn get_reachable_placements(&self) -> Vec<Placement> {
+        generate_placements(&self.state, self.active_piece)
+    }
+
+    // Placement with the fewest key presses which locks the active piece in the cells of the target piece,
+    // starting with a hold if the target is the held piece (or the next piece while nothing is held)
+    pub fn find_finesse_path(&self, target: &Piece) -> Option<Placement> {
+        if target.get_type() == self.active_piece.get_type() {
+            return movegen::find_finesse_path(&self.state, self.active_piece, target);
+        }
+        let held = self.hold_piece.unwrap_or(self.next_piece);
+        if self.hold_used || held.get_type() != target.get_type() {
+            return None;
+        }
+        let mut placement = movegen::find_finesse_path(&self.state, held, target)?;
+        placement.keys.insert(0, KeyPress::Hold);
+        placement.path.insert(0, SingularAction::Hold);
+        Some(placement)
+    }
+
+    // perform the single actions in sequence, stopping early once the piece is locked
+    pub fn perform(&mut self, path: &[SingularAction]) -> StepResult {
+        for action in path.iter() {
+            match self.step(*action) {
+                StepResult::Normal => (),
+                result => return result
+            }
+        }
+        StepResult::Normal
+    }
+
+    // perform the composed action at once without advancing any frames, followed by a hard drop if the piece has not
+    // been locked yet, e.g. to simulate placements in a search
+    pub fn place(&mut self, action: &ComposedAction) -> StepResult {
+        match self.perform(&action.inputs) {
+            StepResult::Normal => self.step(SingularAction::Down),
+            result => result
+        }
+    }
+
+    // A single frame of a player performing a placement: the next of the remaining inputs, then gravity and lock delay
+    // (see Game::tick) unless the input has locked the piece
+    pub fn frame(&mut self, remaining: &mut ComposedAction) -> StepResult {
+        if !remaining.is_empty() {
+            let (input, rest) = remaining.decompose();
+            *remaining = rest;
+            match self.step(input) {
+                StepResult::Normal => (),
+                result => return result
+            }
+        }
+        self.tick(false)
+    }
+
+    // Perform the composed action frame by frame (see Game::frame) like the AI players in the window and the terminal,
+    // followed by a hard drop if the piece has not been locked by the inputs, gravity or the lock delay
+    pub fn play(&mut self, action: &ComposedAction) -> StepResult {
+        let mut remaining = action.clone();
+        while !remaining.is_empty() {
+            match self.frame(&mut remaining) {
+                StepResult::Normal => (),
+                result => return result
+            }
+        }
+        self.step(SingularAction::Down)
+    }
+
+    // events which happened during the last step
+    pub fn get_events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Record every following step and frame, so that the game can be simulated again from its seed (and its start
+    // position for games from Game::from_position). Recording has to start before the first input for the replay to be complete
+    pub fn start_recording(&mut self) {
+        let mut replay = Replay::new(self.seed, self.timing);
+        replay.start_position = self.start_position.clone();
+        self.recording = Some(replay);
+    }
+
+    // Copy of the game for searches and simulations: everything except the recording, which would otherwise be copied
+    // for every node and receive the hypothetical inputs
+    pub fn search_clone(&self) -> Game {
+        Game {
+            state: self.state,
+            active_piece: self.active_piece,
+            next_piece: self.next_piece,
+            hold_piece: self.hold_piece,
+            hold_used: self.hold_used,
+            score: self.score,
+            scoring: self.scoring,
+            events: self.events.clone(),
+            rotated_last: self.rotated_last,
+            timing: self.timing,
+            timer: self.timer,
+            lines_cleared: self.lines_cleared,
+            pieces_placed: self.pieces_placed,
+            piece_counts: self.piece_counts,
+            steps: self.steps,
+            frames: self.frames,
+            seed: self.seed,
+            generator: self.generator.clone(),
+            start_position: self.start_position.clone(),
+            recording: None
+        }
+    }
+
+    // the recorded inputs with the current result of the game
+    pub fn get_replay(&self) -> Option<Replay> {
+        let mut replay = self.recording.clone()?;
+        replay.finish(self);
+        Some(replay)
     }
 
     pub fn step(&mut self, action : SingularAction) -> StepResult {
+        if let Some(recording) = &mut self.recording {
+            recording.record(ReplayInput::Step(action));
+        }
+        self.track_events(|game| game.apply(action))
+    }
+
+    // Advance the game by a single frame: gravity moves the piece down (faster while soft drop is held), and a piece
+    // resting on the ground is locked once the lock delay has passed or the move resets are used up
+    pub fn tick(&mut self, soft_drop: bool) -> StepResult {
+        if let Some(recording) = &mut self.recording {
+            recording.record(ReplayInput::Tick(soft_drop));
+        }
+        self.track_events(|game| game.advance_frame(soft_drop))
+    }
+
+    // run the update with the events of a previous update cleared, and add the changes of score and level
+    fn track_events<F>(&mut self, update: F) -> StepResult
+        where F: FnOnce(&mut Game) -> StepResult {
+        self.events.clear();
+        let score = self.score;
+        let level = self.get_level();
+
+        let result = update(self);
+        if self.score != score {
+            self.events.push(GameEvent::ScoreChanged { delta: self.score - score, score: self.score });
+        }
+        if self.get_level() > level {
+            self.events.push(GameEvent::LevelUp(self.get_level()));
+        }
+        result
+    }
+
+    fn apply(&mut self, action : SingularAction) -> StepResult {
+        self.steps += 1;
         match action {
             SingularAction::None => {
                 let piece = self.active_piece.simulate_next(SingularAction::Down);
                 if self.state.is_valid_state(piece) {
                     self.active_piece = piece;
+                    self.rotated_last = false;
                 } else {
-                    self.state = self.state.integrate_piece(self.active_piece);
-                    let board_update = self.state.remove_full_rows();
-                    self.state = board_update.0;
-                    self.score += board_update.1 + (board_update.1 % 4) * 10;
-                    if !self.state.is_valid_state(self.next_piece){
-                        return StepResult::GameOver;
-                    }
-                    self.active_piece = self.next_piece;
-                    self.next_piece = self.generator.get_next();
-                    return StepResult::NewPiece;
+                    return self.lock_piece();
                 }
             },
-            SingularAction::Left => {
+            SingularAction::Left | SingularAction::Right => {
                 let piece = self.active_piece.simulate_next(action);
                 if self.state.is_valid_state(piece) {
                     self.active_piece = piece;
+                    self.rotated_last = false;
+                    self.restart_lock_delay();
                 }
             },
-            SingularAction::Right => {
+            SingularAction::Down => {
+                let dropped = self.state.hard_drop(self.active_piece);
+                let rows = self.active_piece.position.1 - dropped.position.1;
+                if rows > 0 {
+                    self.active_piece = dropped;
+                    self.score += rows * HARD_DROP_POINTS;
+                    self.rotated_last = false;
+                }
+                return self.lock_piece();
+            },
+            SingularAction::SoftDrop => {
                 let piece = self.active_piece.simulate_next(action);
                 if self.state.is_valid_state(piece) {
                     self.active_piece = piece;
+                    self.score += SOFT_DROP_POINTS;
+                    self.rotated_last = false;
                 }
             },
-            SingularAction::Down => {
-                let mut piece = self.active_piece.simulate_next(action);
-                while self.state.is_valid_state(piece) {
-                    self.active_piece = piece;
-                    piece = piece.simulate_next(action);
-                }
-                self.state = self.state.integrate_piece(self.active_piece);
-                let board_update = self.state.remove_full_rows();
-                self.state = board_update.0;
-                self.score += board_update.1 + (board_update.1 / 4) * 10;
-                if !self.state.is_valid_state(self.next_piece){
-                    return StepResult::GameOver;
-                }
-                self.active_piece = self.next_piece;
-                self.next_piece = self.generator.get_next();
-                return StepResult::NewPiece;
-            }, 
-            SingularAction::Rotate => {
+            SingularAction::Rotate | SingularAction::RotateCounterClockwise | SingularAction::Rotate180 => {
                 let piece = self.active_piece.simulate_next(action);
                 if self.state.is_valid_state(piece) {
                     self.active_piece = piece;
+                    self.rotated_last = true;
+                    self.restart_lock_delay();
+                }
+            },
+            SingularAction::Hold => {
+                if !self.hold_used {
+                    let held = Piece::new_from_idx(self.active_piece.get_type());
+                    match self.hold_piece {
+                        Some(piece) => self.active_piece = piece,
+                        None => {
+                            self.active_piece = self.next_piece;
+                            self.next_piece = self.generator.get_next();
+                        }
+                    }
+                    self.hold_piece = Some(held);
+                    self.hold_used = true;
+                    self.rotated_last = false;
+                    self.timer = PieceTimer::new(self.active_piece.position.1);
+                    self.events.push(GameEvent::HoldUsed { held: held.get_type(), active: self.active_piece.get_type() });
+                    if !self.state.is_valid_state(self.active_piece) {
+                        self.events.push(GameEvent::TopOut(TopOutReason::BlockOut));
+                        return StepResult::GameOver;
+                    }
                 }
             }
         }
         StepResult::Normal
     }
+
+    fn advance_frame(&mut self, soft_drop: bool) -> StepResult {
+        self.frames += 1;
+        let mut gravity = self.timing.gravity(self.get_level());
+        if soft_drop {
+            gravity = (gravity * self.timing.soft_drop_factor).min(GRAVITY_20G);
+        }
+        self.timer.gravity_progress += gravity;
+        while self.timer.gravity_progress >= 1.0 {
+            self.timer.gravity_progress -= 1.0;
+            let piece = self.active_piece.simulate_next(SingularAction::SoftDrop);
+            if !self.state.is_valid_state(piece) {
+                self.timer.gravity_progress = 0.0;
+                break;
+            }
+            self.active_piece = piece;
+            self.rotated_last = false;
+            if soft_drop {
+                self.score += SOFT_DROP_POINTS;
+            }
+        }
+
+        if self.active_piece.position.1 < self.timer.lowest_row {
+            self.timer.lowest_row = self.active_piece.position.1;
+            self.timer.lock_frames = 0;
+            self.timer.move_resets = 0;
+        }
+        if self.state.is_valid_state(self.active_piece.simulate_next(SingularAction::SoftDrop)) {
+            self.timer.lock_frames = 0;
+            return StepResult::Normal;
+        }
+        self.timer.lock_frames += 1;
+        if self.timer.lock_frames >= self.timing.lock_delay || self.timer.move_resets >= self.timing.move_reset_limit {
+            return self.lock_piece();
+        }
+        StepResult::Normal
+    }
+
+    // a successful move or rotation on the ground restarts the lock delay, up to the move reset limit
+    fn restart_lock_delay(&mut self) {
+        if self.timer.lock_frames > 0 && self.timer.move_resets < self.timing.move_reset_limit {
+            self.timer.lock_frames = 0;
+            self.timer.move_resets += 1;
+        }
+    }
+
+    // lock the active piece at its current position, remove the full rows, score the lock and introduce the next piece
+    fn lock_piece(&mut self) -> StepResult {
+        let piece = self.active_piece;
+        let spin = detect_spin(&self.state, &piece, self.rotated_last);
+        let level = self.get_level();
+        let locked = self.state.integrate_piece(piece);
+        let rows = locked.get_full_rows();
+        let (board, removed_rows) = locked.remove_full_rows();
+        self.state = board;
+
+        let lock = self.scoring.lock(piece.get_type(), removed_rows, spin, removed_rows > 0 && board.is_empty(), level);
+        self.score += lock.score;
+        self.lines_cleared += removed_rows;
+        self.pieces_placed += 1;
+        self.piece_counts[(piece.get_type() - 1) as usize] += 1;
+        self.hold_used = false;
+        self.rotated_last = false;
+
+        self.events.push(GameEvent::PieceLocked { piece_type: piece.get_type(), position: piece.position, rotation: piece.rotation });
+        if !rows.is_empty() {
+            self.events.push(GameEvent::LinesCleared { rows });
+        }
+        if spin != Spin::None {
+            self.events.push(GameEvent::Spin(spin));
+        }
+        if lock.combo > 0 {
+            self.events.push(GameEvent::Combo(lock.combo));
+        }
+        if lock.back_to_back {
+            self.events.push(GameEvent::BackToBack);
+        }
+        if lock.perfect_clear {
+            self.events.push(GameEvent::PerfectClear);
+        }
+
+        if is_lock_out(&piece) {
+            self.events.push(GameEvent::TopOut(TopOutReason::LockOut));
+            return StepResult::GameOver;
+        }
+        if !self.state.is_valid_state(self.next_piece){
+            self.events.push(GameEvent::TopOut(TopOutReason::BlockOut));
+            return StepResult::GameOver;
+        }
+        self.active_piece = self.next_piece;
+        self.next_piece = self.generator.get_next();
+        self.timer = PieceTimer::new(self.active_piece.position.1);
+        StepResult::NewPiece
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Play {
     pub previous_state: Features,
     pub next_state: Features,
     pub reward: f64,
     pub action: ComposedAction
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_bags_are_fixed() {
+        let expected : [(u64, &str); 4] = [
+            (0, "ZOILSJTZIJLTSO"),
+            (1, "SJTZOILSTOIJZL"),
+            (42, "JLTIZSOSIJTOZL"),
+            (u64::MAX, "SLJIZOTJOZSILT")
+        ];
+        for (seed, bags) in expected.iter() {
+            let mut generator = PieceGenerator::new(*seed);
+            let pieces : String = (0..14).map(|_| generator.get_next().get_name()).collect();
+            assert_eq!(pieces, *bags, "pieces of seed {}", seed);
+        }
+        let game = Game::with_seed(42);
+        assert_eq!((game.active_piece.get_name(), game.next_piece.get_name()), ("J", "L"));
+    }
+
+    #[test]
+    fn board_text_round_trip() {
+        let text = "....OO....\nSZJLIOTX..\nXXXXXXXXX.";
+        let board : Board = text.parse().unwrap();
+        assert_eq!(board.state[0], [GARBAGE, GARBAGE, GARBAGE, GARBAGE, GARBAGE, GARBAGE, GARBAGE, GARBAGE, GARBAGE, 0]);
+        assert_eq!(board.state[2][4], Piece::from_name('O').unwrap().get_type());
+        assert_eq!(board.to_string(), text);
+        // rows separated by '/', lower case letters and short rows
+        let other : Board = "....oo/szjliotg/XXXXXXXXX.".parse().unwrap();
+        assert_eq!(other.state, board.state);
+        assert_eq!(Board::new().to_string(), "..........");
+    }
+
+    #[test]
+    fn board_text_errors() {
+        for text in ["...........", "XXXXXXXXXXX\n..........", "....Q.....", "...-......"].iter() {
+            let error = text.parse::<Board>().err().unwrap_or_else(|| panic!("{} should not parse", text));
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        let too_high = vec![".........."; GAME_HEIGHT + 1].join("\n");
+        assert!(too_high.parse::<Board>().is_err());
+        assert!(vec![".........."; GAME_HEIGHT].join("\n").parse::<Board>().is_ok());
+    }
+
+    #[test]
+    fn from_position_rejects_overlap() {
+        let board : Board = "XXXXXXXXX.".parse().unwrap();
+        let overlapping = Piece::new_at(Piece::from_name('T').unwrap().get_type(), (3, 1), 2);
+        assert!(!board.is_valid_state(overlapping));
+        let error = Game::from_position(board, overlapping, &[], None, 0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(Game::from_position(board, Piece::from_name('T').unwrap(), &[], None, 0).is_ok());
+    }
+
+    #[test]
+    fn search_clone_does_not_record() {
+        let mut game = Game::with_seed(3);
+        game.start_recording();
+        game.step(SingularAction::Left);
+        let mut copy = game.search_clone();
+        assert!(copy.get_replay().is_none());
+        copy.step(SingularAction::Down);
+        assert_eq!((copy.active_piece.get_type(), copy.pieces_placed), (game.next_piece.get_type(), 1));
+        assert_eq!(game.get_replay().unwrap().num_inputs(), 1);
+    }
+
+    #[test]
+    fn positioned_game_replay_verifies() {
+        let board : Board = "XXXX......\nXXX...XXXX\nXXXX.XXXXX".parse().unwrap();
+        let piece = |name: char| Piece::from_name(name).unwrap();
+        let mut game = Game::from_position(board, piece('T'), &[piece('S'), piece('Z')], Some(piece('I')), 7).unwrap();
+        assert_eq!(game.next_piece.get_name(), "S");
+        game.start_recording();
+        let actions = [SingularAction::Left, SingularAction::Rotate, SingularAction::Down, SingularAction::Hold,
+                       SingularAction::Down, SingularAction::Right, SingularAction::Down, SingularAction::Down];
+        for action in actions.iter() {
+            game.step(*action);
+        }
+        assert_eq!(game.pieces_placed, 4);
+        let replay = game.get_replay().unwrap();
+        let verification = replay.verify().unwrap();
+        assert!(verification.matches);
+        assert_eq!(verification.game.state.state, game.state.state);
+
+        // the start position is kept in the text format
+        let loaded = Replay::from_text(&replay.to_text()).unwrap();
+        let start = loaded.start_position.as_ref().unwrap();
+        assert_eq!(start.board.state, board.state);
+        assert_eq!(start.queue.iter().map(|piece| piece.get_name()).collect::<String>(), "SZ");
+        assert_eq!(start.hold_piece.map(|piece| piece.get_name()), Some("I"));
+        assert_eq!(loaded.to_text(), replay.to_text());
+        assert!(loaded.verify().unwrap().matches);
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs;
+use std::io;
+
+use rand::{thread_rng, Rng};
+use rand_distr::{Distribution, Normal};
+
+use crate::game;
+use crate::linear;
+use crate::evaluation;
+
+use game::HEURISTIC_LENGTH;
+use linear::LinearEvaluator;
+use evaluation::EvaluationConfig;
+
+// Settings of the genetic algorithm
+#[derive(Clone, Copy)]
+pub struct GeneticConfig {
+    pub population_size: usize,
+    pub tournament_size: usize,
+    pub crossover_rate: f64,
+    pub mutation_rate: f64, // probability of mutating each weight
+    pub mutation_stddev: f64,
+    pub elitism: usize, // best individuals copied unchanged into the next generation
+    pub initial_range: f64,
+    pub games_per_individual: usize,
+    pub max_pieces: i32,
+    pub hall_of_fame_size: usize,
+    pub first_seed: u64
+}
+
+impl Default for GeneticConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeneticConfig {
+    pub fn new() -> GeneticConfig {
+        GeneticConfig {
+            population_size: 50,
+            tournament_size: 5,
+            crossover_rate: 0.9,
+            mutation_rate: 0.2,
+            mutation_stddev: 1.0,
+            elitism: 2,
+            initial_range: 10.0,
+            games_per_individual: 3,
+            max_pieces: 1000,
+            hall_of_fame_size: 10,
+            first_seed: 0
+        }
+    }
+}
+
+// Statistics of a single generation
+#[derive(Clone, Copy, Debug)]
+pub struct GenerationStatistics {
+    pub generation: usize,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+    pub worst_fitness: f64,
+    pub stddev_fitness: f64
+}
+
+// Genetic algorithm over the weights of the linear evaluation: tournament selection, uniform crossover
+// and gaussian mutation, the fitness is the average number of cleared lines in seeded games
+pub struct GeneticAlgorithm {
+    config: GeneticConfig,
+    population: Vec<LinearEvaluator>,
+    generation: usize,
+    // best individuals found so far, sorted by fitness
+    pub hall_of_fame: Vec<(LinearEvaluator, f64)>
+}
+
+impl GeneticAlgorithm {
+    pub fn new(config: GeneticConfig) -> GeneticAlgorithm {
+        let mut rng = thread_rng();
+        let mut population = Vec::new();
+        for _ in 0..config.population_size {
+            let mut weights = [0.0; HEURISTIC_LENGTH];
+            for weight in weights.iter_mut() {
+                *weight = rng.gen_range(-config.initial_range, config.initial_range);
+            }
+            population.push(LinearEvaluator::new(weights));
+        }
+
+        GeneticAlgorithm {
+            config,
+            population,
+            generation: 0,
+            hall_of_fame: Vec::new()
+        }
+    }
+
+    // All individuals of a generation play the same games
+    fn fitness(&self, evaluator: &LinearEvaluator) -> f64 {
+        let config = EvaluationConfig {
+            num_games: self.config.games_per_individual,
+            first_seed: self.config.first_seed + (self.generation * self.config.games_per_individual) as u64,
+            max_pieces: self.config.max_pieces
+        };
+        evaluation::evaluate(config, |game| evaluator.select_action(game)).lines.mean
+    }
+
+    fn tournament<'a>(&self, evaluated: &'a [(LinearEvaluator, f64)]) -> &'a LinearEvaluator {
+        let mut rng = thread_rng();
+        let mut winner = &evaluated[rng.gen_range(0, evaluated.len())];
+        for _ in 1..self.config.tournament_size {
+            let candidate = &evaluated[rng.gen_range(0, evaluated.len())];
+            if candidate.1 > winner.1 {
+                winner = candidate;
+            }
+        }
+        &winner.0
+    }
+
+    fn crossover(&self, first: &LinearEvaluator, second: &LinearEvaluator) -> LinearEvaluator {
+        let mut rng = thread_rng();
+        let mut child = *first;
+        if rng.gen::<f64>() < self.config.crossover_rate {
+            for i in 0..HEURISTIC_LENGTH {
+                if rng.gen() {
+                    child.weights[i] = second.weights[i];
+                }
+            }
+        }
+        child
+    }
+
+    fn mutate(&self, evaluator: &mut LinearEvaluator) {
+        let mut rng = thread_rng();
+        let normal = Normal::new(0.0, self.config.mutation_stddev).unwrap();
+        for weight in evaluator.weights.iter_mut() {
+            if rng.gen::<f64>() < self.config.mutation_rate {
+                *weight += normal.sample(&mut rng);
+            }
+        }
+    }
+
+    // Elites are carried over unchanged and evaluated again, so individuals already in the hall of fame are not added again
+    fn update_hall_of_fame(&mut self, evaluated: &[(LinearEvaluator, f64)]) {
+        for (evaluator, fitness) in evaluated.iter() {
+            if !self.hall_of_fame.iter().any(|(member, _)| member.weights == evaluator.weights) {
+                self.hall_of_fame.push((*evaluator, *fitness));
+            }
+        }
+        self.hall_of_fame.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        self.hall_of_fame.truncate(self.config.hall_of_fame_size);
+    }
+
+    // Evaluate the current generation and replace it by its offspring
+    pub fn evolve(&mut self) -> GenerationStatistics {
+        let mut evaluated : Vec<(LinearEvaluator, f64)> = Vec::new();
+        for evaluator in self.population.iter() {
+            evaluated.push((*evaluator, self.fitness(evaluator)));
+        }
+        evaluated.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        self.update_hall_of_fame(&evaluated);
+
+        let mut offspring : Vec<LinearEvaluator> = evaluated.iter().take(self.config.elitism).map(|(evaluator, _)| *evaluator).collect();
+        while offspring.len() < self.config.population_size {
+            let mut child = self.crossover(self.tournament(&evaluated), self.tournament(&evaluated));
+            self.mutate(&mut child);
+            offspring.push(child);
+        }
+        self.population = offspring;
+
+        let count = evaluated.len() as f64;
+        let mean_fitness = evaluated.iter().map(|(_, fitness)| fitness).sum::<f64>() / count;
+        let variance = evaluated.iter().map(|(_, fitness)| (fitness - mean_fitness).powi(2)).sum::<f64>() / count;
+        let statistics = GenerationStatistics {
+            generation: self.generation,
+            best_fitness: evaluated[0].1,
+            mean_fitness,
+            worst_fitness: evaluated[evaluated.len() - 1].1,
+            stddev_fitness: variance.sqrt()
+        };
+        self.generation += 1;
+        statistics
+    }
+
+    // One individual per line, best first, so the file can be read by LinearEvaluator::load
+    pub fn save_hall_of_fame(&self, path: &str) -> io::Result<()> {
+        let lines : Vec<String> = self.hall_of_fame.iter().map(|(evaluator, _)| evaluator.to_line()).collect();
+        fs::write(path, lines.join("\n") + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hall_of_fame_has_no_duplicates() {
+        let mut config = GeneticConfig::new();
+        config.hall_of_fame_size = 3;
+        let mut genetic = GeneticAlgorithm::new(config);
+        let best = LinearEvaluator::new([1.0; HEURISTIC_LENGTH]);
+        let other = LinearEvaluator::new([2.0; HEURISTIC_LENGTH]);
+        for generation in 0..3 {
+            genetic.update_hall_of_fame(&[(best, 10.0 + generation as f64), (best, 9.0), (other, 5.0)]);
+        }
+        assert_eq!(genetic.hall_of_fame.len(), 2);
+        assert_eq!(genetic.hall_of_fame[0].0.weights, best.weights);
+        assert_eq!(genetic.hall_of_fame[1].0.weights, other.weights);
+    }
+}
@@ -0,0 +1,69 @@
+// Results of the episodes of a training run, used to plot its progress
+pub struct TrainingHistory {
+    pub scores: Vec<f64>,
+    pub lines: Vec<f64>,
+    // averages over the decisions of each episode, only recorded for agents which report them
+    pub td_errors: Vec<f64>,
+    pub exploration_rates: Vec<f64>,
+
+    // sums over the decisions of the current episode
+    episode_td_error: f64,
+    episode_exploration_rate: f64,
+    episode_decisions: usize
+}
+
+impl Default for TrainingHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrainingHistory {
+    pub fn new() -> TrainingHistory {
+        TrainingHistory {
+            scores: Vec::new(),
+            lines: Vec::new(),
+            td_errors: Vec::new(),
+            exploration_rates: Vec::new(),
+            episode_td_error: 0.0,
+            episode_exploration_rate: 0.0,
+            episode_decisions: 0
+        }
+    }
+
+    pub fn num_episodes(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn record_decision(&mut self, td_error: f64, exploration_rate: f64) {
+        self.episode_td_error += td_error;
+        self.episode_exploration_rate += exploration_rate;
+        self.episode_decisions += 1;
+    }
+
+    pub fn end_episode(&mut self, score: i32, lines: i32) {
+        self.scores.push(score as f64);
+        self.lines.push(lines as f64);
+        if self.episode_decisions > 0 {
+            self.td_errors.push(self.episode_td_error / self.episode_decisions as f64);
+            self.exploration_rates.push(self.episode_exploration_rate / self.episode_decisions as f64);
+        }
+        self.episode_td_error = 0.0;
+        self.episode_exploration_rate = 0.0;
+        self.episode_decisions = 0;
+    }
+}
+
+// mean over the last `window` values up to each value
+pub fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    let mut averages = Vec::new();
+    let mut sum = 0.0;
+    for i in 0..values.len() {
+        sum += values[i];
+        if i >= window {
+            sum -= values[i - window];
+        }
+        averages.push(sum / (i + 1).min(window) as f64);
+    }
+    averages
+}
@@ -0,0 +1,175 @@
+use crate::game;
+
+use game::*;
+
+// Controls of a human player, independent of the keys they are bound to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateClockwise,
+    RotateCounterClockwise,
+    Rotate180,
+    Hold,
+    Pause, // handled by the front-end, not by the input handler
+    Restart
+}
+
+// Timings of held keys in frames (see timing::FRAMES_PER_SECOND)
+#[derive(Clone, Copy)]
+pub struct InputConfig {
+    pub das: u32, // delayed auto shift: frames a direction has to be held before it repeats
+    pub arr: u32 // auto repeat rate: frames between repeated shifts, 0 moves the piece to the wall at once
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputConfig {
+    pub fn new() -> InputConfig {
+        InputConfig {
+            das: 10,
+            arr: 2
+        }
+    }
+}
+
+// the most severe result of multiple steps
+fn combine(first: StepResult, second: StepResult) -> StepResult {
+    match (first, second) {
+        (StepResult::GameOver, _) | (_, StepResult::GameOver) => StepResult::GameOver,
+        (StepResult::NewPiece, _) | (_, StepResult::NewPiece) => StepResult::NewPiece,
+        _ => StepResult::Normal
+    }
+}
+
+// Turns presses and releases of the controls into actions of the game, repeating held directions
+pub struct InputHandler {
+    pub config: InputConfig,
+    left_frames: Option<u32>, // frames the direction has been held, None if released
+    right_frames: Option<u32>,
+    last_direction: Option<Control>, // the direction pressed last wins while both are held
+    soft_drop: bool
+}
+
+impl InputHandler {
+    pub fn new(config: InputConfig) -> InputHandler {
+        InputHandler {
+            config,
+            left_frames: None,
+            right_frames: None,
+            last_direction: None,
+            soft_drop: false
+        }
+    }
+
+    pub fn press(&mut self, game: &mut Game, control: Control) -> StepResult {
+        match control {
+            Control::Left => {
+                self.left_frames = Some(0);
+                self.last_direction = Some(Control::Left);
+                game.step(SingularAction::Left)
+            },
+            Control::Right => {
+                self.right_frames = Some(0);
+                self.last_direction = Some(Control::Right);
+                game.step(SingularAction::Right)
+            },
+            Control::SoftDrop => {
+                self.soft_drop = true;
+                StepResult::Normal
+            },
+            Control::HardDrop => game.step(SingularAction::Down),
+            Control::RotateClockwise => game.step(SingularAction::Rotate),
+            Control::RotateCounterClockwise => game.step(SingularAction::RotateCounterClockwise),
+            Control::Rotate180 => game.step(SingularAction::Rotate180),
+            Control::Hold => game.step(SingularAction::Hold),
+            Control::Pause | Control::Restart => StepResult::Normal
+        }
+    }
+
+    pub fn release(&mut self, control: Control) {
+        match control {
+            Control::Left => {
+                self.left_frames = None;
+                if self.right_frames.is_some() {
+                    self.last_direction = Some(Control::Right);
+                }
+            },
+            Control::Right => {
+                self.right_frames = None;
+                if self.left_frames.is_some() {
+                    self.last_direction = Some(Control::Left);
+                }
+            },
+            Control::SoftDrop => self.soft_drop = false,
+            _ => ()
+        }
+    }
+
+    // Advance a single frame: repeat the held direction once the delayed auto shift has passed, then advance the game.
+    // An ARR of 0 moves the piece to the wall at once. The remainder is used instead of u32::is_multiple_of,
+    // which would require Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn update(&mut self, game: &mut Game) -> StepResult {
+        let (frames, action) = match self.last_direction {
+            Some(Control::Left) => (&mut self.left_frames, SingularAction::Left),
+            Some(Control::Right) => (&mut self.right_frames, SingularAction::Right),
+            _ => return game.tick(self.soft_drop)
+        };
+
+        let mut result = StepResult::Normal;
+        if let Some(held) = frames {
+            *held += 1;
+            if *held >= self.config.das {
+                let repeats = if self.config.arr == 0 {
+                    GAME_WIDTH as u32
+                } else if (*held - self.config.das) % self.config.arr == 0 {
+                    1
+                } else {
+                    0
+                };
+                for _ in 0..repeats {
+                    result = combine(result, game.step(action));
+                }
+            }
+        }
+        combine(result, game.tick(self.soft_drop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // horizontal position of the active piece after holding right for the given number of frames
+    fn hold_right(config: InputConfig, frames: u32) -> (Game, i32) {
+        let mut game = Game::with_seed(0);
+        let start = game.active_piece.get_position().0;
+        let mut input = InputHandler::new(config);
+        input.press(&mut game, Control::Right);
+        for _ in 0..frames {
+            input.update(&mut game);
+        }
+        let shift = game.active_piece.get_position().0 - start;
+        (game, shift)
+    }
+
+    #[test]
+    fn delayed_auto_shift() {
+        let config = InputConfig { das: 10, arr: 2 };
+        assert_eq!(hold_right(config, 9).1, 1);
+        // repeats on the frame the delay has passed and every second frame after it
+        assert_eq!(hold_right(config, 10).1, 2);
+        assert_eq!(hold_right(config, 13).1, 3);
+
+        let (game, _) = hold_right(InputConfig { das: 10, arr: 0 }, 10);
+        assert!(!game.state.is_valid_state(Piece::new_at(game.active_piece.get_type(),
+            (game.active_piece.get_position().0 + 1, game.active_piece.get_position().1), game.active_piece.get_rotation())));
+    }
+}
@@ -0,0 +1,23 @@
+pub mod game;
+pub mod agent;
+pub mod network;
+pub mod evaluation;
+pub mod linear;
+pub mod cem;
+pub mod genetic;
+pub mod actor_critic;
+pub mod search;
+pub mod expectimax;
+pub mod mcts;
+pub mod movegen;
+pub mod scoring;
+pub mod timing;
+pub mod input;
+pub mod bindings;
+pub mod font;
+pub mod history;
+pub mod raster;
+pub mod terminal;
+pub mod svg;
+pub mod replay;
+pub mod fumen;
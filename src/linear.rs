@@ -0,0 +1,72 @@
+use std::fs;
+use std::io;
+
+use crate::game;
+
+use game::*;
+
+// Linear evaluation of game states: weighted sum of the heuristic features of the state after an action
+#[derive(Clone, Copy, Debug)]
+pub struct LinearEvaluator {
+    pub weights: [f64; HEURISTIC_LENGTH]
+}
+
+impl LinearEvaluator {
+    pub fn new(weights: [f64; HEURISTIC_LENGTH]) -> LinearEvaluator {
+        LinearEvaluator {
+            weights
+        }
+    }
+
+    pub fn evaluate(&self, features: &Features) -> f64 {
+        let vector = features.get_heuristic_vector();
+        let mut value = 0.0;
+        for (weight, feature) in self.weights.iter().zip(vector.iter()) {
+            value += weight * feature;
+        }
+        value
+    }
+
+    // Greedy action: the placement leading to the highest valued state
+    pub fn select_action(&self, game: &Game) -> Option<ComposedAction> {
+        let (possible_actions, _, sim_features) = game.get_possible_actions();
+        let mut best : Option<(ComposedAction, f64)> = None;
+        for (i, action) in possible_actions.iter().enumerate() {
+            let value = self.evaluate(&sim_features[i]);
+            match best {
+                Some((_, best_value)) if best_value >= value => (),
+                _ => best = Some((action.clone(), value))
+            }
+        }
+        best.map(|(action, _)| action)
+    }
+
+    // Weights are stored as a single line of whitespace separated numbers
+    pub fn to_line(&self) -> String {
+        let weights : Vec<String> = self.weights.iter().map(|weight| weight.to_string()).collect();
+        weights.join(" ")
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_line() + "\n")
+    }
+
+    // Load the weights from the first line of the file
+    pub fn load(path: &str) -> io::Result<LinearEvaluator> {
+        let content = fs::read_to_string(path)?;
+        let line = content.lines().next().unwrap_or("");
+        let mut weights = [0.0; HEURISTIC_LENGTH];
+        let mut count = 0;
+        for value in line.split_whitespace() {
+            if count == HEURISTIC_LENGTH {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "too many weights"));
+            }
+            weights[count] = value.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid weight: {}", value)))?;
+            count += 1;
+        }
+        if count != HEURISTIC_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too few weights"));
+        }
+        Ok(LinearEvaluator::new(weights))
+    }
+}
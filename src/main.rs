@@ -4,38 +4,172 @@ extern crate opengl_graphics;
 extern crate piston;
 
 use std::env;
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
 use glutin_window::GlutinWindow as Window;
 use opengl_graphics::{GlGraphics, OpenGL};
 use piston::event_loop::{EventSettings, Events};
-use piston::input::{RenderArgs, RenderEvent, UpdateEvent, Button, PressEvent, Key};
+use piston::input::{RenderArgs, RenderEvent, UpdateEvent, Button, PressEvent, ReleaseEvent, Key};
 use piston::window::{WindowSettings, AdvancedWindow};
-use graphics::{clear, Rectangle, Transformed};
+use graphics::{clear, Context, Line, Rectangle, Transformed};
 
 const BLOCK_WIDTH : f64 = 30.0;
 const BLOCK_HEIGHT : f64 = 30.0;
 const LEFT_MARGIN : f64 = 15.0;
 const TOP_MARGIN : f64 = 15.0;
 const BORDER_WIDTH : f64 = 0.5;
+const SIDE_PANEL_WIDTH : f64 = 8.0 * BLOCK_WIDTH;
+const PANEL_LEFT : f64 = 2.0 * LEFT_MARGIN + RENDER_WIDTH as f64 * BLOCK_WIDTH;
+const PREVIEW_WIDTH : f64 = 4.0 * BLOCK_WIDTH;
+const WINDOW_HEIGHT : f64 = 2.0 * TOP_MARGIN + RENDER_HEIGHT as f64 * BLOCK_HEIGHT;
+const CHART_WIDTH : f64 = SIDE_PANEL_WIDTH - LEFT_MARGIN;
+const CHART_HEIGHT : f64 = 40.0;
+const MOVING_AVERAGE_WINDOW : usize = 20;
+const TEXT_SCALE : f64 = 2.0; // size of a pixel of the bitmap font
+const LINE_HEIGHT : f64 = 14.0;
 
 const BLACK : [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 const WHITE : [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 const BORDER_COLOR : [f32; 4] = WHITE;
+const GHOST_ALPHA : f32 = 0.3;
+const CHOSEN_COLOR : [f32; 4] = WHITE;
+const OVERLAY_TOP_K : usize = 5;
+const RAW_COLOR : [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const AVERAGE_COLOR : [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const REPLAY_DIRECTORY : &str = "replays";
+const TERMINAL_AI_FRAMES : u32 = 10; // frames between two placements of the AI in the terminal
 
-pub mod game;
-pub mod agent;
-pub mod network;
+use tetris::game::*;
+use tetris::agent::Agent;
+use tetris::evaluation::{self, EvaluationConfig};
+use tetris::linear::LinearEvaluator;
+use tetris::cem::{CemConfig, CrossEntropyMethod};
+use tetris::genetic::{GeneticConfig, GeneticAlgorithm};
+use tetris::actor_critic::{ActorCritic, PolicyUpdate};
+use tetris::search::{BeamSearch, LeafEvaluation};
+use tetris::expectimax::Expectimax;
+use tetris::mcts::{Mcts, MctsEvaluation};
+use tetris::timing::FRAME_DURATION;
+use tetris::input::{Control, InputConfig, InputHandler};
+use tetris::bindings::KeyBindings;
+use tetris::font;
+use tetris::history::{self, TrainingHistory};
+use tetris::raster::{self, GifRecorder};
+use tetris::terminal::{self, Terminal, TerminalInput};
+use tetris::svg;
+use tetris::replay::{Playback, Replay, ReplayInput};
+use tetris::fumen;
 
-use game::*;
-use agent::Agent;
+// Candidate placements of the agent with their estimated values, drawn on top of the board
+pub struct Overlay {
+    candidates: Vec<(Piece, f64)>,
+    chosen: usize,
+    explored: bool,
+    exploration_rate: f64,
+    learning_rate: f64,
+    td_error: f64
+}
+
+// State of the AI shown in the side panel
+pub struct AiInfo<'a> {
+    overlay: Option<&'a Overlay>,
+    history: &'a TrainingHistory
+}
+
+impl Overlay {
+    fn from_agent(game: &Game, possible_actions: &[ComposedAction], agent: &Agent) -> Option<Overlay> {
+        let decision = agent.get_last_decision()?;
+        let mut candidates = Vec::new();
+        for (i, action) in possible_actions.iter().enumerate() {
+            candidates.push((game.simulate_inputs(&action.inputs)?, decision.action_values[i]));
+        }
+        Some(Overlay {
+            candidates,
+            chosen: decision.chosen,
+            explored: decision.explored,
+            exploration_rate: agent.get_exploration_rate(),
+            learning_rate: agent.get_learning_rate(),
+            td_error: agent.get_td_error()
+        })
+    }
+}
+
+// red for the lowest and green for the highest value
+fn value_color(value: f64, min_value: f64, max_value: f64) -> [f32; 4] {
+    let t = if max_value > min_value { ((value - min_value) / (max_value - min_value)) as f32 } else { 1.0 };
+    [1.0 - t, t, 0.0, 0.8]
+}
+
+// transform of the top left corner of a cell of the board
+fn cell_transform(c: &Context, x: i32, y: i32) -> [[f64; 3]; 2] {
+    c.transform
+        .trans(LEFT_MARGIN, TOP_MARGIN)
+        .trans(x as f64 * BLOCK_WIDTH, (RENDER_HEIGHT as i32 - 1 - y) as f64 * BLOCK_HEIGHT)
+}
+
+// text in the bitmap font with its top left corner at (x, y)
+fn draw_text(text: &str, x: f64, y: f64, color: [f32; 4], c: &Context, gl: &mut GlGraphics) {
+    let pixel = Rectangle::new(color);
+    for (i, j) in font::text_pixels(text) {
+        pixel.draw([x + i as f64 * TEXT_SCALE, y + j as f64 * TEXT_SCALE, TEXT_SCALE, TEXT_SCALE], &c.draw_state, c.transform, gl);
+    }
+}
+
+// piece in its spawn rotation with the top left corner of its shape at (x, y)
+fn draw_preview(piece: &Piece, x: f64, y: f64, square: [f64; 4], c: &Context, gl: &mut GlGraphics) {
+    for j in 0..4 {
+        for i in 0..4 {
+            if piece.shape[0][j][i] != 0 {
+                let transform = c.transform.trans(x + i as f64 * BLOCK_WIDTH, y + j as f64 * BLOCK_HEIGHT);
+                Rectangle::new(piece.color).draw(square, &c.draw_state, transform, gl);
+            }
+        }
+    }
+}
+
+// line chart of the last values of the series with a common scale, the label on top
+fn draw_chart(label: &str, series: &[(&[f64], [f32; 4])], x: f64, y: f64, c: &Context, gl: &mut GlGraphics) {
+    draw_text(label, x, y, WHITE, c, gl);
+    let top = y + LINE_HEIGHT;
+    Rectangle::new_border(RAW_COLOR, BORDER_WIDTH).draw([x, top, CHART_WIDTH, CHART_HEIGHT], &c.draw_state, c.transform, gl);
+
+    let num_points = CHART_WIDTH as usize;
+    let mut min_value = f64::INFINITY;
+    let mut max_value = f64::NEG_INFINITY;
+    for (values, _) in series.iter() {
+        for value in values[values.len().saturating_sub(num_points)..].iter() {
+            min_value = min_value.min(*value);
+            max_value = max_value.max(*value);
+        }
+    }
+    let range = if max_value > min_value { max_value - min_value } else { 1.0 };
+
+    for (values, color) in series.iter() {
+        let values = &values[values.len().saturating_sub(num_points)..];
+        if values.len() < 2 {
+            continue;
+        }
+        let step = CHART_WIDTH / (values.len() - 1) as f64;
+        let point = |i: usize| (x + i as f64 * step, top + CHART_HEIGHT - (values[i] - min_value) / range * CHART_HEIGHT);
+        for i in 1..values.len() {
+            let (x0, y0) = point(i - 1);
+            let (x1, y1) = point(i);
+            Line::new(*color, 0.5).draw([x0, y0, x1, y1], &c.draw_state, c.transform, gl);
+        }
+    }
+}
 
 pub struct App {
     gl: GlGraphics
 }
 
 impl App {
-    fn render(&mut self, args: &RenderArgs, board: Board, next_piece: Piece) {
-        
-        
+    fn render(&mut self, args: &RenderArgs, game: &Game, ai: Option<AiInfo>) {
+        let board = game.state.simulate_board(game.active_piece);
+        let next_piece = game.next_piece;
+        let ghost = game.get_ghost_piece();
+
         let square = [1.0, 1.0, BLOCK_WIDTH - 1.0, BLOCK_HEIGHT - 1.0];
         self.gl.draw(args.viewport(), |c, gl| {
             // Clear the screen.
@@ -51,6 +185,16 @@ impl App {
                     &c.draw_state, 
                     transform, 
                     gl);
+            // Ghost piece: translucent at the position where the active piece would land
+            let mut ghost_color = ghost.color;
+            ghost_color[3] = GHOST_ALPHA;
+            for (i, j) in ghost.get_cells().iter() {
+                if *j >= RENDER_HEIGHT as i32 {
+                    continue;
+                }
+                Rectangle::new(ghost_color).draw(square, &c.draw_state, cell_transform(&c, *i, *j), gl);
+            }
+
             // Current state / pieces: simple rectangle for each "pixel"
             for j in 0..RENDER_HEIGHT {
                 for i in 0..RENDER_WIDTH {
@@ -62,23 +206,104 @@ impl App {
                         .trans(LEFT_MARGIN, TOP_MARGIN)
                         .trans(i as f64 * BLOCK_WIDTH, (RENDER_HEIGHT - 1 - j) as f64 * BLOCK_HEIGHT);
 
-                    let color = PIECE_COLORS[(board.state[j][i] - 1) as usize];
+                    let color = get_cell_color(board.state[j][i]);
                     Rectangle::new(color).draw(square, &c.draw_state, transform, gl);
                 }
             }
 
-            // Draw next shape to the side of the board
-            let next_shape = next_piece.shape;
-            let transform = c.transform.trans(
-                2.0 * LEFT_MARGIN + GAME_WIDTH as f64 * BLOCK_WIDTH,
-                TOP_MARGIN + 100.0);
-            for j in 0..4 {
-                for i in 0..4 {
-                    if next_shape[0][j][i] != 0 {
-                        let trans = transform.trans(i as f64 * BLOCK_WIDTH, j as f64 * BLOCK_HEIGHT);
-                        Rectangle::new(next_piece.color).draw(square, &c.draw_state, trans, gl);
+            // Side panel: next and held piece, statistics of the game and the state of the AI
+            let x = PANEL_LEFT;
+            draw_text("NEXT", x, TOP_MARGIN, WHITE, &c, gl);
+            draw_preview(&next_piece, x, TOP_MARGIN + LINE_HEIGHT, square, &c, gl);
+            draw_text("HOLD", x + PREVIEW_WIDTH, TOP_MARGIN, WHITE, &c, gl);
+            if let Some(hold_piece) = game.hold_piece {
+                draw_preview(&hold_piece, x + PREVIEW_WIDTH, TOP_MARGIN + LINE_HEIGHT, square, &c, gl);
+            }
+
+            let mut lines = vec![
+                format!("SCORE {}", game.score),
+                format!("LEVEL {}  LINES {}", game.get_level(), game.lines_cleared),
+                format!("PIECES {}  PPS {:.2}", game.pieces_placed, game.get_pieces_per_second())
+            ];
+            let mut combo = if game.scoring.combo > 0 { format!("COMBO {}", game.scoring.combo) } else { String::from("COMBO -") };
+            if game.scoring.back_to_back {
+                combo.push_str("  B2B");
+            }
+            lines.push(combo);
+            let counts : Vec<String> = PIECE_NAMES.iter().zip(game.piece_counts.iter()).map(|(name, count)| format!("{} {}", name, count)).collect();
+            lines.push(counts[..4].join(" "));
+            lines.push(counts[4..].join(" "));
+            if let Some(ai) = &ai {
+                lines.push(String::new());
+                lines.push(format!("EPISODE {}", ai.history.num_episodes() + 1));
+                if let Some(overlay) = ai.overlay {
+                    lines.push(format!("EPS {:.3}  LR {:.5}", overlay.exploration_rate, overlay.learning_rate));
+                }
+            }
+            let mut y = TOP_MARGIN + LINE_HEIGHT + 2.0 * BLOCK_HEIGHT + LINE_HEIGHT;
+            for line in lines.iter() {
+                draw_text(line, x, y, WHITE, &c, gl);
+                y += LINE_HEIGHT;
+            }
+
+            if let Some(overlay) = ai.as_ref().and_then(|ai| ai.overlay) {
+                // outline of every candidate placement coloured by its value, the chosen placement on top
+                let min_value = overlay.candidates.iter().map(|candidate| candidate.1).fold(f64::INFINITY, f64::min);
+                let max_value = overlay.candidates.iter().map(|candidate| candidate.1).fold(f64::NEG_INFINITY, f64::max);
+                for (k, (piece, value)) in overlay.candidates.iter().enumerate() {
+                    if k == overlay.chosen {
+                        continue;
+                    }
+                    let color = value_color(*value, min_value, max_value);
+                    for (i, j) in piece.get_cells().iter() {
+                        if *j < RENDER_HEIGHT as i32 {
+                            Rectangle::new_border(color, 1.0).draw(square, &c.draw_state, cell_transform(&c, *i, *j), gl);
+                        }
+                    }
+                }
+                if let Some((piece, _)) = overlay.candidates.get(overlay.chosen) {
+                    for (i, j) in piece.get_cells().iter() {
+                        if *j < RENDER_HEIGHT as i32 {
+                            Rectangle::new_border(CHOSEN_COLOR, 2.5).draw(square, &c.draw_state, cell_transform(&c, *i, *j), gl);
+                        }
                     }
                 }
+
+                // the decision and the best values
+                y += LINE_HEIGHT;
+                let decision = if overlay.explored { "EXPLORED" } else { "GREEDY" };
+                draw_text(decision, x, y, WHITE, &c, gl);
+                y += LINE_HEIGHT;
+                let mut ranking : Vec<usize> = (0..overlay.candidates.len()).collect();
+                ranking.sort_by(|a, b| overlay.candidates[*b].1.partial_cmp(&overlay.candidates[*a].1).unwrap_or(std::cmp::Ordering::Equal));
+                for (rank, k) in ranking.iter().take(OVERLAY_TOP_K).enumerate() {
+                    let color = if *k == overlay.chosen { CHOSEN_COLOR } else { value_color(overlay.candidates[*k].1, min_value, max_value) };
+                    draw_text(&format!("{} {:8.3}", rank + 1, overlay.candidates[*k].1), x, y, color, &c, gl);
+                    y += LINE_HEIGHT;
+                }
+                if let Some(rank) = ranking.iter().position(|k| *k == overlay.chosen) {
+                    draw_text(&format!("CHOSEN {}/{}", rank + 1, ranking.len()), x, y, CHOSEN_COLOR, &c, gl);
+                }
+            }
+
+            // training curves at the bottom of the side panel: raw values per episode and their moving average
+            if let Some(history) = ai.as_ref().map(|ai| ai.history) {
+                let mut y = WINDOW_HEIGHT - TOP_MARGIN - 4.0 * (LINE_HEIGHT + CHART_HEIGHT + 2.0);
+                let curves : [(&str, &[f64]); 4] = [
+                    ("SCORE", &history.scores),
+                    ("LINES", &history.lines),
+                    ("TD ERROR", &history.td_errors),
+                    ("EXPLORATION", &history.exploration_rates)
+                ];
+                for (name, values) in curves.iter() {
+                    let average = history::moving_average(values, MOVING_AVERAGE_WINDOW);
+                    let label = match average.last() {
+                        Some(last) => format!("{} AVG {:.3}", name, last),
+                        None => name.to_string()
+                    };
+                    draw_chart(&label, &[(values, RAW_COLOR), (&average, AVERAGE_COLOR)], x, y, &c, gl);
+                    y += LINE_HEIGHT + CHART_HEIGHT + 2.0;
+                }
             }
         });
     }
@@ -89,7 +314,7 @@ fn init_window_and_app() -> (Window, App) {
 
     let window: Window = WindowSettings::new(
         "tretris game",
-         [3.0 * LEFT_MARGIN + (RENDER_WIDTH + 4) as f64 * BLOCK_WIDTH, 2.0 * TOP_MARGIN + RENDER_HEIGHT as f64 * BLOCK_HEIGHT])
+         [3.0 * LEFT_MARGIN + RENDER_WIDTH as f64 * BLOCK_WIDTH + SIDE_PANEL_WIDTH, 2.0 * TOP_MARGIN + RENDER_HEIGHT as f64 * BLOCK_HEIGHT])
             .graphics_api(opengl)
             .exit_on_esc(true)
             .build()
@@ -103,114 +328,655 @@ fn init_window_and_app() -> (Window, App) {
 }
 
 // Run game with input from the user
-fn run_user_input(window: &mut Window, app: &mut App) {
-    let mut game = Game::new();
-    let mut level = game.get_level();
-    let mut fall_speed = 0.1 * ((1 - level) as f64 / 3.0).exp();
-    window.set_title(format!("Tetris Game - Score: {} - Level: {}", game.score, level));
+fn run_user_input(window: &mut Window, app: &mut App, config: InputConfig, bindings: KeyBindings) {
+    let mut game = new_recorded_game();
+    let mut input = InputHandler::new(config);
+    let mut paused = false;
+    window.set_title(format!("Tetris Game - Score: {} - Level: {}", game.score, game.get_level()));
 
     let mut time = 0.0;
 
     let mut events = Events::new(EventSettings::new());
     while let Some(e) = events.next(window) {
+        // keys are bound by the name of the piston key
         if let Some(Button::Keyboard(key)) = e.press_args() {
-            if key == Key::Up {
-                game.step(SingularAction::Rotate);
-            } 
-            if key == Key::Left {
-                game.step(SingularAction::Left);
-            } 
-            if key == Key::Right {
-                game.step(SingularAction::Right);
-            } 
-            if key == Key::Down {
-                game.step(SingularAction::Down);
+            match bindings.get_control(&format!("{:?}", key)) {
+                Some(Control::Pause) => paused = !paused,
+                Some(Control::Restart) => {
+                    save_replay(&game);
+                    game = new_recorded_game();
+                    paused = false;
+                },
+                Some(control) if !paused => {
+                    if let StepResult::GameOver = input.press(&mut game, control) {
+                        save_replay(&game);
+                        game = new_recorded_game();
+                    }
+                },
+                _ => ()
+            }
+        }
+        if let Some(Button::Keyboard(key)) = e.release_args() {
+            if let Some(control) = bindings.get_control(&format!("{:?}", key)) {
+                input.release(control);
             }
         }
         if let Some(args) = e.render_args() {
-            app.render(&args, game.state.simulate_board(game.active_piece), game.next_piece);
+            app.render(&args, &game, None);
         }
         if let Some(args) = e.update_args() {
+            if paused {
+                continue;
+            }
+            // gravity and lock delay advance in fixed frames
             time += args.dt;
-            if time >= fall_speed {
-                time = 0.0;
-                match game.step(SingularAction::None) {
-                    StepResult::GameOver => game = Game::new(),
-                    _ => ()
+            while time >= FRAME_DURATION {
+                time -= FRAME_DURATION;
+                if let StepResult::GameOver = input.update(&mut game) {
+                    save_replay(&game);
+                    game = new_recorded_game();
                 }
-                level = game.get_level();
-                fall_speed = 0.1 * ((1 - level) as f64 / 3.0).exp(); // 0.07
-                window.set_title(format!("Tetris Game - Score: {} - Level: {}", game.score, level));
             }
+            window.set_title(format!("Tetris Game - Score: {} - Level: {}", game.score, game.get_level()));
         }
     }
+    save_replay(&game);
 }
 
-// run game with AI and training
-fn run_agent_input(window: &mut Window, app: &mut App) {
+// game which records its inputs, see save_replay
+fn new_recorded_game() -> Game {
     let mut game = Game::new();
-    let mut level = game.get_level();
-    let mut fall_speed = 0.1 * ((1 - level) as f64 / 3.0).exp();
-    window.set_title(format!("Tetris Game - Score: {} - Level: {}", game.score, level));
+    game.start_recording();
+    game
+}
 
-    let mut agent = Agent::new();
-    let mut action = ComposedAction::new();
-    let mut new_piece = true; // wether a new piece was added during the previous time step
+// write the replay of a finished game to the replay directory, named by the seed of the game
+fn save_replay(game: &Game) {
+    let replay = match game.get_replay() {
+        Some(replay) if replay.pieces > 0 => replay,
+        _ => return
+    };
+    let path = format!("{}/{}.replay", REPLAY_DIRECTORY, replay.seed);
+    if let Err(error) = fs::create_dir_all(REPLAY_DIRECTORY).and_then(|_| replay.save(&path)) {
+        println!("Could not write replay to {}: {}", path, error);
+    }
+}
+
+// apply the inputs of the replay up to the next frame, or up to the next locked piece for replays without frames
+// (e.g. of games played with Game::place)
+fn playback_frame(playback: &mut Playback) {
+    while let Some(input) = playback.peek() {
+        let result = playback.advance();
+        if let ReplayInput::Tick(_) = input {
+            return;
+        }
+        if let Some(StepResult::NewPiece) | Some(StepResult::GameOver) = result {
+            return;
+        }
+    }
+}
+
+// print whether the simulated game reached the recorded result
+fn print_verification(replay: &Replay, game: &Game, matches: bool) {
+    println!("Recorded - Score: {} - Lines: {} - Pieces: {}", replay.score, replay.lines, replay.pieces);
+    println!("Simulated - Score: {} - Lines: {} - Pieces: {}", game.score, game.lines_cleared, game.pieces_placed);
+    println!("{}", if matches { "The replay matches the recorded result" } else { "The replay does NOT match the recorded result" });
+}
+
+// Watch a replay in real time. P or space pauses, N advances a single frame while paused, R starts the replay again
+fn run_replay(window: &mut Window, app: &mut App, replay: &Replay) -> io::Result<()> {
+    let mut playback = Playback::new(replay)?;
+    let mut paused = false;
+    let mut verified = false;
 
     let mut time = 0.0;
 
     let mut events = Events::new(EventSettings::new());
     while let Some(e) = events.next(window) {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            match key {
+                Key::P | Key::Space => paused = !paused,
+                Key::N if paused => playback_frame(&mut playback),
+                Key::R => {
+                    playback = Playback::new(replay)?;
+                    verified = false;
+                },
+                _ => ()
+            }
+        }
         if let Some(args) = e.render_args() {
-            app.render(&args, game.state.simulate_board(game.active_piece), game.next_piece);
+            app.render(&args, &playback.game, None);
         }
         if let Some(args) = e.update_args() {
-            time += args.dt;
-            // only get the AI actions when a new piece is introduced
-            if new_piece {
-                new_piece = false;
-                let features = Features::build(game.state); // get the features of the current game state
-                let possible_actions = game.get_possible_actions(); // list all posible actions
-                action = agent.td_learning(features, possible_actions.0, possible_actions.1, possible_actions.2); // run the update/learning step
-            }
-            // the sampled action is decomposed and executed over multiple time steps, returning the next to take action and the remaining actions
-            let decomposed = action.decompose();
-            action = decomposed.1; // remaining actions to be taken in future time steps
-            match game.step(decomposed.0) { // action to be taken in this time step
-                StepResult::NewPiece => new_piece = true,
-                _ => ()
+            if !paused {
+                time += args.dt;
+                while time >= FRAME_DURATION {
+                    time -= FRAME_DURATION;
+                    playback_frame(&mut playback);
+                }
+            }
+            if playback.is_finished() && !verified {
+                print_verification(replay, &playback.game, playback.matches_result());
+                verified = true;
             }
+            let state = if playback.is_finished() { "finished" } else if paused { "paused" } else { "playing" };
+            window.set_title(format!("Tetris Replay - Score: {} - Level: {} - {}", playback.game.score, playback.game.get_level(), state));
+        }
+    }
+    Ok(())
+}
 
-            if time >= fall_speed { // piece drops a single row
-                time = 0.0;
-                match game.step(SingularAction::None) {
-                    StepResult::GameOver => {
-                        game = Game::new();
-                        action = ComposedAction::new();
-                    },
-                    StepResult::NewPiece => { // the piece has reached the "ground"
-                        new_piece = true;
+// run game with AI and training
+fn run_agent_input(window: &mut Window, app: &mut App) {
+    let mut agent = Agent::new();
+    run_ai_input_with_overlay(window, app, |game| {
+        let features = Features::build(game.state); // get the features of the current game state
+        let possible_actions = game.get_possible_actions(); // list all posible actions
+        if possible_actions.0.is_empty() {
+            return None;
+        }
+        let candidates = possible_actions.0.clone();
+        let action = agent.td_learning(features, possible_actions.0, possible_actions.1, possible_actions.2); // run the update/learning step
+        Some((action, Overlay::from_agent(game, &candidates, &agent)))
+    });
+}
+
+// Simulation speed of the AI viewer
+#[derive(Clone, Copy)]
+enum Speed {
+    Multiplier(f64), // game time relative to real time
+    Uncapped(usize) // placements played at once between two rendered frames
+}
+
+const SPEEDS : [Speed; 12] = [Speed::Multiplier(0.25), Speed::Multiplier(0.5), Speed::Multiplier(1.0), Speed::Multiplier(2.0),
+                              Speed::Multiplier(4.0), Speed::Multiplier(8.0), Speed::Multiplier(16.0), Speed::Multiplier(32.0),
+                              Speed::Multiplier(64.0), Speed::Uncapped(1), Speed::Uncapped(10), Speed::Uncapped(100)];
+const DEFAULT_SPEED : usize = 2;
+
+impl std::fmt::Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Speed::Multiplier(multiplier) => write!(f, "{}x", multiplier),
+            Speed::Uncapped(pieces) => write!(f, "uncapped, rendering every {} pieces", pieces)
+        }
+    }
+}
+
+// Game played by an AI policy, which selects the action whenever a new piece is introduced
+struct AiPlayer<F> {
+    game: Game,
+    action: ComposedAction, // remaining inputs of the selected placement
+    overlay: Option<Overlay>,
+    history: TrainingHistory,
+    new_piece: bool, // wether a new piece was added since the last selection
+    select_action: F
+}
+
+impl<F> AiPlayer<F>
+    where F: FnMut(&Game) -> Option<(ComposedAction, Option<Overlay>)> {
+    fn new(select_action: F) -> AiPlayer<F> {
+        AiPlayer {
+            game: new_recorded_game(),
+            action: ComposedAction::new(),
+            overlay: None,
+            history: TrainingHistory::new(),
+            new_piece: true,
+            select_action
+        }
+    }
+
+    // only get the AI actions when a new piece is introduced
+    fn select(&mut self) {
+        if !self.new_piece {
+            return;
+        }
+        self.new_piece = false;
+        let selection = (self.select_action)(&self.game);
+        self.action = selection.as_ref().map(|selection| selection.0.clone()).unwrap_or_default();
+        self.overlay = selection.and_then(|selection| selection.1);
+        if let Some(overlay) = &self.overlay {
+            self.history.record_decision(overlay.td_error, overlay.exploration_rate);
+        }
+    }
+
+    fn handle(&mut self, result: StepResult) {
+        match result {
+            StepResult::GameOver => {
+                self.history.end_episode(self.game.score, self.game.lines_cleared);
+                self.restart();
+            },
+            StepResult::NewPiece => { // the piece has been locked on the "ground"
+                self.action = ComposedAction::new();
+                self.new_piece = true;
+            },
+            StepResult::Normal => ()
+        }
+    }
+
+    fn restart(&mut self) {
+        save_replay(&self.game);
+        self.game = new_recorded_game();
+        self.action = ComposedAction::new();
+        self.new_piece = true;
+    }
+
+    // a single frame: the next input of the selected placement, then gravity and lock delay
+    fn frame(&mut self) {
+        self.select();
+        // the selected action is executed over multiple frames, one input per frame
+        let result = self.game.frame(&mut self.action);
+        self.handle(result);
+    }
+
+    // the remaining inputs of the selected placement without rendering the frames in between
+    fn placement(&mut self) {
+        self.select();
+        let result = self.game.play(&self.action);
+        self.action = ComposedAction::new();
+        self.handle(result);
+    }
+}
+
+// run game with an AI policy, which selects the action whenever a new piece is introduced
+fn run_ai_input<F>(window: &mut Window, app: &mut App, mut select_action: F)
+    where F: FnMut(&Game) -> Option<ComposedAction> {
+    run_ai_input_with_overlay(window, app, |game| select_action(game).map(|action| (action, None)));
+}
+
+// run game with an AI policy, which also returns an overlay explaining its decision.
+// Viewer controls: P or space pauses, N plays a single placement while paused, +/- change the speed, R restarts the game
+fn run_ai_input_with_overlay<F>(window: &mut Window, app: &mut App, select_action: F)
+    where F: FnMut(&Game) -> Option<(ComposedAction, Option<Overlay>)> {
+    let mut player = AiPlayer::new(select_action);
+    let mut speed = DEFAULT_SPEED;
+    let mut paused = false;
+
+    let mut time = 0.0;
+
+    let mut events = Events::new(EventSettings::new());
+    while let Some(e) = events.next(window) {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            match key {
+                Key::P | Key::Space => paused = !paused,
+                Key::N if paused => player.placement(),
+                Key::Equals | Key::Plus | Key::NumPadPlus => speed = (speed + 1).min(SPEEDS.len() - 1),
+                Key::Minus | Key::NumPadMinus => speed = speed.saturating_sub(1),
+                Key::R => player.restart(),
+                _ => ()
+            }
+        }
+        if let Some(args) = e.render_args() {
+            app.render(&args, &player.game, Some(AiInfo { overlay: player.overlay.as_ref(), history: &player.history }));
+        }
+        if let Some(args) = e.update_args() {
+            if !paused {
+                match SPEEDS[speed] {
+                    Speed::Multiplier(multiplier) => {
+                        // the game advances in fixed frames
+                        time += args.dt * multiplier;
+                        while time >= FRAME_DURATION {
+                            time -= FRAME_DURATION;
+                            player.frame();
+                        }
                     },
-                    StepResult::Normal => ()
+                    Speed::Uncapped(pieces) => {
+                        for _ in 0..pieces {
+                            player.placement();
+                        }
+                    }
                 }
-                level = game.get_level();
-                fall_speed = 0.1 * ((1 - level) as f64 / 3.0).exp(); // 0.07
-                window.set_title(format!("Tetris Game - Score: {} - Level: {}", game.score, level));
             }
+            let state = if paused { String::from("paused") } else { SPEEDS[speed].to_string() };
+            window.set_title(format!("Tetris Game - Score: {} - Level: {} - Speed: {}", player.game.score, player.game.get_level(), state));
+        }
+    }
+    save_replay(&player.game);
+}
+
+// train the agent without rendering, then evaluate its greedy policy on seeded games
+fn run_evaluation(training_episodes: usize, num_games: usize) {
+    let mut agent = Agent::new();
+    for episode in 0..training_episodes {
+        let result = evaluation::play_game(Game::new(), 1000, |game| {
+            let features = Features::build(game.state);
+            let possible_actions = game.get_possible_actions();
+            if possible_actions.0.is_empty() {
+                return None;
+            }
+            Some(agent.td_learning(features, possible_actions.0, possible_actions.1, possible_actions.2))
+        });
+        println!("Episode {} - Score: {} - Lines: {}", episode + 1, result.score, result.lines);
+    }
+
+    let mut config = EvaluationConfig::new();
+    config.num_games = num_games;
+    println!("Greedy policy:");
+    println!("{}", evaluation::evaluate_agent(&agent, config));
+
+    // the same value network with lookahead on the next piece
+    let search = BeamSearch::new(8, 2, LeafEvaluation::ValueNetwork(&agent));
+    println!("Beam search:");
+    println!("{}", evaluation::evaluate(config, |game| search.select_action(game)));
+
+    // averaging over the pieces left in the bag for the placement after the next piece
+    let search = Expectimax::new(3, 8, LeafEvaluation::ValueNetwork(&agent));
+    println!("Expectimax:");
+    println!("{}", evaluation::evaluate(config, |game| search.select_action(game)));
+
+    let search = Mcts::new(200, MctsEvaluation::ValueNetwork(&agent));
+    println!("Monte Carlo Tree Search:");
+    println!("{}", evaluation::evaluate(config, |game| search.select_action(game)));
+}
+
+// optimize linear evaluation weights with the cross-entropy method, the best weights are written to the output file
+fn run_cem(iterations: usize, output: &str) {
+    let mut cem = CrossEntropyMethod::new(CemConfig::new());
+    for _ in 0..iterations {
+        let statistics = cem.iterate();
+        println!("Iteration {} - Mean: {:.2} - Elite: {:.2} - Best: {:.2} - Stddev: {:.3}",
+            statistics.iteration + 1, statistics.mean_fitness, statistics.elite_fitness, statistics.best_fitness, statistics.mean_stddev);
+        if let Err(error) = cem.best.save(output) {
+            println!("Could not write weights to {}: {}", output, error);
+        }
+    }
+    println!("Best weights ({:.2} lines): {:?}", cem.best_fitness, cem.best.weights);
+}
+
+// evolve linear evaluation weights with a genetic algorithm, the hall of fame is written to the output file
+fn run_genetic(generations: usize, output: &str) {
+    let mut genetic = GeneticAlgorithm::new(GeneticConfig::new());
+    for _ in 0..generations {
+        let statistics = genetic.evolve();
+        println!("Generation {} - Best: {:.2} - Mean: {:.2} - Worst: {:.2} - Stddev: {:.2}",
+            statistics.generation + 1, statistics.best_fitness, statistics.mean_fitness, statistics.worst_fitness, statistics.stddev_fitness);
+        if let Err(error) = genetic.save_hall_of_fame(output) {
+            println!("Could not write hall of fame to {}: {}", output, error);
+        }
+    }
+    for (evaluator, fitness) in genetic.hall_of_fame.iter() {
+        println!("{:8.2} lines: {:?}", fitness, evaluator.weights);
+    }
+}
+
+// train the actor-critic agent without rendering, then evaluate its greedy policy on seeded games
+fn run_actor_critic(episodes: usize, update: PolicyUpdate) {
+    let mut agent = ActorCritic::new(update);
+    for episode in 0..episodes {
+        let result = evaluation::play_game(Game::new(), 1000, |game| agent.select_action(game));
+        let advantage = agent.end_episode();
+        println!("Episode {} - Score: {} - Lines: {} - Mean Advantage: {:.3}", episode + 1, result.score, result.lines, advantage);
+    }
+
+    println!("{}", evaluation::evaluate(EvaluationConfig::new(), |game| agent.greedy_action(game)));
+}
+
+// play in the terminal, or watch the linear evaluator play if it is given. Terminals only report key presses
+// (repeated while a key is held), so every press moves the piece once and soft drop moves it down a single row
+fn run_terminal(bindings: KeyBindings, evaluator: Option<LinearEvaluator>) -> io::Result<()> {
+    let mut terminal = Terminal::new()?;
+    let mut game = new_recorded_game();
+    let mut input = InputHandler::new(InputConfig::new());
+    let mut paused = false;
+    let mut frames = 0;
+    let frame_duration = Duration::from_secs_f64(FRAME_DURATION);
+    let mut next_frame = Instant::now() + frame_duration;
+    loop {
+        let status = if paused { "PAUSED" } else if evaluator.is_some() { "AI" } else { "" };
+        terminal.draw(&terminal::render_game(&game, status))?;
+
+        let now = Instant::now();
+        if now < next_frame {
+            let key = match terminal.read_input(next_frame - now)? {
+                Some(TerminalInput::Quit) => {
+                    save_replay(&game);
+                    return Ok(());
+                },
+                Some(TerminalInput::Key(key)) => key,
+                None => continue
+            };
+            let result = match bindings.get_control(&key) {
+                Some(Control::Pause) => {
+                    paused = !paused;
+                    StepResult::Normal
+                },
+                Some(Control::Restart) => {
+                    paused = false;
+                    StepResult::GameOver
+                },
+                Some(Control::SoftDrop) if !paused && evaluator.is_none() => game.step(SingularAction::SoftDrop),
+                Some(control) if !paused && evaluator.is_none() => {
+                    let result = input.press(&mut game, control);
+                    input.release(control);
+                    result
+                },
+                _ => StepResult::Normal
+            };
+            if let StepResult::GameOver = result {
+                save_replay(&game);
+                game = new_recorded_game();
+            }
+            continue;
+        }
+
+        next_frame += frame_duration;
+        if paused {
+            continue;
+        }
+        let result = match &evaluator {
+            Some(evaluator) => {
+                frames += 1;
+                if frames < TERMINAL_AI_FRAMES {
+                    continue;
+                }
+                frames = 0;
+                match evaluator.select_action(&game) {
+                    Some(action) => game.play(&action),
+                    None => StepResult::GameOver
+                }
+            },
+            None => input.update(&mut game)
+        };
+        if let StepResult::GameOver = result {
+            save_replay(&game);
+            game = new_recorded_game();
+        }
+    }
+}
+
+// play a game with the linear evaluator without a window, recording a GIF frame per placed piece and a PNG of the final position
+fn run_gif(evaluator: &LinearEvaluator, max_pieces: i32, output: &str) -> io::Result<()> {
+    let mut recorder = GifRecorder::new(output, 10)?;
+    let mut game = new_recorded_game();
+    while game.pieces_placed < max_pieces {
+        recorder.add_frame(&raster::render_game(&game))?;
+        let action = match evaluator.select_action(&game) {
+            Some(action) => action,
+            None => break
+        };
+        if let StepResult::GameOver = game.play(&action) {
+            break;
+        }
+    }
+    save_replay(&game);
+    let image = raster::render_game(&game);
+    recorder.add_frame(&image)?;
+    image.save_png(&format!("{}.png", output.trim_end_matches(".gif")))?;
+    println!("Score: {} - Lines: {} - Pieces: {}", game.score, game.lines_cleared, game.pieces_placed);
+    Ok(())
+}
+
+// write the board given by its description (or a file containing it) as SVG, with the active piece spawned and
+// its ghost, and the next pieces given by their letters
+fn run_svg(description: &str, output: &str, active: Option<&str>, next: Option<&str>) -> io::Result<()> {
+    let description = fs::read_to_string(description).unwrap_or_else(|_| description.to_string());
+    let board : Board = description.parse()?;
+    let invalid_piece = |name: char| io::Error::new(io::ErrorKind::InvalidData, format!("unknown piece: {}", name));
+    let active_piece = match active.and_then(|name| name.chars().next()) {
+        Some(name) => Some(Piece::from_name(name).ok_or_else(|| invalid_piece(name))?),
+        None => None
+    };
+    let mut next_pieces = Vec::new();
+    for name in next.unwrap_or("").chars() {
+        next_pieces.push(Piece::from_name(name).ok_or_else(|| invalid_piece(name))?);
+    }
+    svg::save_svg(output, &svg::board_to_svg(&board, active_piece, true, &next_pieces))
+}
+
+// print the pages of a fumen, or encode the placements of a replay file as fumen
+fn run_fumen(input: &str) -> io::Result<()> {
+    if !input.contains("v115@") {
+        println!("{}", fumen::encode(&fumen::pages_from_replay(&Replay::load(input)?)?)?);
+        return Ok(());
+    }
+    for (k, page) in fumen::decode(input)?.iter().enumerate() {
+        let piece = page.piece.map(|piece| piece.get_name()).unwrap_or("-");
+        println!("Page {} - Piece: {} - Comment: {}", k + 1, piece, page.comment);
+        match page.piece {
+            Some(piece) => println!("{}", page.board.simulate_board(piece)),
+            None => println!("{}", page.board)
+        }
+    }
+    Ok(())
+}
+
+fn load_bindings(path: &str) -> KeyBindings {
+    match KeyBindings::load(path) {
+        Ok(bindings) => bindings,
+        Err(error) => {
+            println!("Using the default controls, could not read key bindings from {}: {}", path, error);
+            KeyBindings::new()
         }
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "evaluate" {
+        let training_episodes = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(100);
+        let num_games = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(20);
+        run_evaluation(training_episodes, num_games);
+        return;
+    }
+    if args.len() > 1 && args[1] == "cem" {
+        let iterations = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(20);
+        let output = args.get(3).map(|arg| arg.as_str()).unwrap_or("weights.txt");
+        run_cem(iterations, output);
+        return;
+    }
+    if args.len() > 1 && args[1] == "genetic" {
+        let generations = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(20);
+        let output = args.get(3).map(|arg| arg.as_str()).unwrap_or("hall_of_fame.txt");
+        run_genetic(generations, output);
+        return;
+    }
+    if args.len() > 1 && args[1] == "actor-critic" {
+        let episodes = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(100);
+        let update = if args.get(3).map(|arg| arg.as_str()) == Some("reinforce") { PolicyUpdate::Reinforce } else { PolicyUpdate::Ppo };
+        run_actor_critic(episodes, update);
+        return;
+    }
+    if args.len() > 1 && args[1] == "tui" {
+        let evaluator = match args.get(2).map(|path| LinearEvaluator::load(path)) {
+            Some(Ok(evaluator)) => Some(evaluator),
+            Some(Err(error)) => {
+                println!("Could not read weights from {}: {}", args[2], error);
+                return;
+            },
+            None => None
+        };
+        if let Err(error) = run_terminal(load_bindings("controls.txt"), evaluator) {
+            println!("Terminal error: {}", error);
+        }
+        return;
+    }
+    if args.len() > 2 && (args[1] == "replay" || args[1] == "verify") {
+        let replay = match Replay::load(&args[2]) {
+            Ok(replay) => replay,
+            Err(error) => {
+                println!("Could not read replay from {}: {}", args[2], error);
+                return;
+            }
+        };
+        if args[1] == "verify" {
+            match replay.verify() {
+                Ok(verification) => print_verification(&replay, &verification.game, verification.matches),
+                Err(error) => println!("Could not simulate replay {}: {}", args[2], error)
+            }
+        } else {
+            let (mut window, mut app) = init_window_and_app();
+            if let Err(error) = run_replay(&mut window, &mut app, &replay) {
+                println!("Could not simulate replay {}: {}", args[2], error);
+            }
+        }
+        return;
+    }
+    if args.len() > 2 && args[1] == "fumen" {
+        if let Err(error) = run_fumen(&args[2]) {
+            println!("Could not convert {}: {}", args[2], error);
+        }
+        return;
+    }
+    if args.len() > 2 && args[1] == "svg" {
+        let output = args.get(3).map(|arg| arg.as_str()).unwrap_or("board.svg");
+        if let Err(error) = run_svg(&args[2], output, args.get(4).map(|arg| arg.as_str()), args.get(5).map(|arg| arg.as_str())) {
+            println!("Could not write {}: {}", output, error);
+        }
+        return;
+    }
+    if args.len() > 2 && args[1] == "gif" {
+        let evaluator = match LinearEvaluator::load(&args[2]) {
+            Ok(evaluator) => evaluator,
+            Err(error) => {
+                println!("Could not read weights from {}: {}", args[2], error);
+                return;
+            }
+        };
+        let max_pieces = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(100);
+        let output = args.get(4).map(|arg| arg.as_str()).unwrap_or("game.gif");
+        if let Err(error) = run_gif(&evaluator, max_pieces, output) {
+            println!("Could not write {}: {}", output, error);
+        }
+        return;
+    }
+
     let window_and_app = init_window_and_app();
     let mut window = window_and_app.0;
     let mut app = window_and_app.1;
     if args.len() > 1 && args[1] == "user" {
-        run_user_input(&mut window, &mut app);
+        let mut config = InputConfig::new();
+        config.das = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(config.das);
+        config.arr = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(config.arr);
+        let bindings = load_bindings(args.get(4).map(|arg| arg.as_str()).unwrap_or("controls.txt"));
+        run_user_input(&mut window, &mut app, config, bindings);
+    } else if args.len() > 2 && (args[1] == "linear" || args[1] == "beam" || args[1] == "expectimax" || args[1] == "mcts") {
+        let evaluator = match LinearEvaluator::load(&args[2]) {
+            Ok(evaluator) => evaluator,
+            Err(error) => {
+                println!("Could not read weights from {}: {}", args[2], error);
+                return;
+            }
+        };
+        if args[1] == "linear" {
+            run_ai_input(&mut window, &mut app, |game| evaluator.select_action(game));
+        } else if args[1] == "beam" {
+            let width = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(8);
+            let depth = args.get(4).and_then(|arg| arg.parse().ok()).unwrap_or(2);
+            let search = BeamSearch::new(width, depth, LeafEvaluation::Heuristic(evaluator));
+            run_ai_input(&mut window, &mut app, |game| search.select_action(game));
+        } else if args[1] == "expectimax" {
+            let depth = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(3);
+            let branching = args.get(4).and_then(|arg| arg.parse().ok()).unwrap_or(8);
+            let search = Expectimax::new(depth, branching, LeafEvaluation::Heuristic(evaluator));
+            run_ai_input(&mut window, &mut app, |game| search.select_action(game));
+        } else {
+            let iterations = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(500);
+            let mut search = Mcts::new(iterations, MctsEvaluation::Rollout(evaluator, 5));
+            search.time_limit = Some(Duration::from_millis(200));
+            run_ai_input(&mut window, &mut app, |game| search.select_action(game));
+        }
     } else {
         run_agent_input(&mut window, &mut app);
     }
 }
 
-// TODO: allow rotation even with collision - allow multiple actions at once, smoother Left/Right pressing
\ No newline at end of file
+// TODO: allow rotation even with collision
\ No newline at end of file
@@ -0,0 +1,315 @@
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, Rng};
+
+use crate::game;
+use crate::agent;
+use crate::linear;
+
+use game::*;
+use agent::Agent;
+use linear::LinearEvaluator;
+
+// Evaluation of a newly expanded placement
+#[derive(Clone, Copy)]
+pub enum MctsEvaluation<'a> {
+    // play greedily with the linear evaluation for a number of placements, drawing random pieces from the bag
+    Rollout(LinearEvaluator, usize),
+    // value of the resulting state estimated by the network of the agent
+    ValueNetwork(&'a Agent)
+}
+
+// Placement of the active piece at a decision node
+struct Edge {
+    action: ComposedAction,
+    reward: f64,
+    features: Features,
+    visits: u32,
+    total_value: f64,
+    chance: Option<usize>
+}
+
+impl Edge {
+    fn mean_value(&self) -> f64 {
+        if self.visits == 0 { 0.0 } else { self.total_value / self.visits as f64 }
+    }
+}
+
+// State in which a placement has to be chosen, the active and the next piece are known
+struct DecisionNode {
+    game: Game,
+    remaining: Vec<u8>,
+    edges: Vec<Edge>,
+    visits: u32
+}
+
+// State after a placement: the next piece becomes active and a new piece is drawn from the bag
+struct ChanceNode {
+    board: Board,
+    active_piece: Piece,
+    bag: Vec<u8>,
+    children: Vec<Option<usize>>
+}
+
+// Result of a search: the chosen placement, the statistics of all placements at the root and the value backed up to the root
+pub struct MctsResult {
+    pub action: ComposedAction, // most visited placement
+    // mean return over all iterations, usable as improved target for the value of the current state (see Agent::train_value)
+    pub value: f64,
+    pub action_statistics: Vec<(ComposedAction, u32, f64)> // visits and mean value of every placement at the root
+}
+
+// Monte Carlo Tree Search with UCT selection over the placements and chance nodes for the piece draws
+pub struct Mcts<'a> {
+    pub iterations: usize,
+    pub time_limit: Option<Duration>,
+    pub exploration: f64,
+    pub discount_factor: f64,
+    pub evaluation: MctsEvaluation<'a>
+}
+
+struct Tree {
+    decisions: Vec<DecisionNode>,
+    chances: Vec<ChanceNode>
+}
+
+impl<'a> Mcts<'a> {
+    pub fn new(iterations: usize, evaluation: MctsEvaluation<'a>) -> Mcts<'a> {
+        let discount_factor = match evaluation {
+            MctsEvaluation::Rollout(_, _) => 0.9,
+            MctsEvaluation::ValueNetwork(agent) => agent.get_discount_factor()
+        };
+        Mcts {
+            iterations,
+            time_limit: None,
+            exploration: 1.0,
+            discount_factor,
+            evaluation
+        }
+    }
+
+    // most visited placement
+    pub fn select_action(&self, game: &Game) -> Option<ComposedAction> {
+        self.search(game).map(|result| result.action)
+    }
+
+    // Run the search until the iteration or time budget is used up
+    pub fn search(&self, game: &Game) -> Option<MctsResult> {
+        let mut tree = Tree {
+            decisions: Vec::new(),
+            chances: Vec::new()
+        };
+        let root = self.add_decision(&mut tree, game.search_clone(), game.get_bag_remaining());
+        if tree.decisions[root].edges.is_empty() {
+            return None;
+        }
+
+        let start = Instant::now();
+        for _ in 0..self.iterations {
+            if let Some(limit) = self.time_limit {
+                if start.elapsed() >= limit {
+                    break;
+                }
+            }
+            self.simulate_decision(&mut tree, root);
+        }
+
+        let decision = &tree.decisions[root];
+        let edges = &decision.edges;
+        let mut best = 0;
+        for (i, edge) in edges.iter().enumerate() {
+            if edge.visits > edges[best].visits {
+                best = i;
+            }
+        }
+        let total_value : f64 = edges.iter().map(|edge| edge.total_value).sum();
+        Some(MctsResult {
+            action: edges[best].action.clone(),
+            value: if decision.visits == 0 { 0.0 } else { total_value / decision.visits as f64 },
+            action_statistics: edges.iter().map(|edge| (edge.action.clone(), edge.visits, edge.mean_value())).collect()
+        })
+    }
+
+    fn add_decision(&self, tree: &mut Tree, game: Game, remaining: Vec<u8>) -> usize {
+        let (possible_actions, rewards, sim_features) = game.get_possible_actions();
+        let edges = possible_actions.iter().enumerate().map(|(i, action)| Edge {
+            action: action.clone(),
+            reward: rewards[i],
+            features: sim_features[i],
+            visits: 0,
+            total_value: 0.0,
+            chance: None
+        }).collect();
+        tree.decisions.push(DecisionNode {
+            game,
+            remaining,
+            edges,
+            visits: 0
+        });
+        tree.decisions.len() - 1
+    }
+
+    // UCT, the mean values are normalized to [0, 1] among the placements as the rewards are not bounded
+    fn select_edge(&self, node: &DecisionNode) -> usize {
+        if let Some(unvisited) = node.edges.iter().position(|edge| edge.visits == 0) {
+            return unvisited;
+        }
+        let values : Vec<f64> = node.edges.iter().map(|edge| edge.mean_value()).collect();
+        let min_value = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_value = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let range = if max_value > min_value { max_value - min_value } else { 1.0 };
+
+        let mut best = 0;
+        let mut best_score = f64::NEG_INFINITY;
+        for (i, edge) in node.edges.iter().enumerate() {
+            let exploitation = (values[i] - min_value) / range;
+            let exploration = self.exploration * ((node.visits as f64).ln() / edge.visits as f64).sqrt();
+            if exploitation + exploration > best_score {
+                best_score = exploitation + exploration;
+                best = i;
+            }
+        }
+        best
+    }
+
+    // one iteration starting at a decision node, returns the discounted return of the chosen placement
+    fn simulate_decision(&self, tree: &mut Tree, node: usize) -> f64 {
+        if tree.decisions[node].edges.is_empty() {
+            return 0.0;
+        }
+        let edge = self.select_edge(&tree.decisions[node]);
+        let (reward, features, visits) = {
+            let edge = &tree.decisions[node].edges[edge];
+            (edge.reward, edge.features, edge.visits)
+        };
+
+        let future_value = if features.is_terminal {
+            0.0
+        } else if visits == 0 {
+            self.evaluate(tree, node, edge)
+        } else {
+            let chance = match tree.decisions[node].edges[edge].chance {
+                Some(chance) => chance,
+                None => self.add_chance(tree, node, edge)
+            };
+            self.simulate_chance(tree, chance)
+        };
+        let value = reward + self.discount_factor * future_value;
+
+        let decision = &mut tree.decisions[node];
+        decision.visits += 1;
+        decision.edges[edge].visits += 1;
+        decision.edges[edge].total_value += value;
+        value
+    }
+
+    fn add_chance(&self, tree: &mut Tree, node: usize, edge: usize) -> usize {
+        let decision = &tree.decisions[node];
+        let board = decision.game.simulate_placement(&decision.edges[edge].action).unwrap().0;
+        let bag : Vec<u8> = if decision.remaining.is_empty() { (1..=7).collect() } else { decision.remaining.clone() };
+        let children = vec![None; bag.len()];
+        tree.chances.push(ChanceNode {
+            board,
+            active_piece: decision.game.next_piece,
+            bag,
+            children
+        });
+        let chance = tree.chances.len() - 1;
+        tree.decisions[node].edges[edge].chance = Some(chance);
+        chance
+    }
+
+    // draw the next piece uniformly from the bag
+    fn simulate_chance(&self, tree: &mut Tree, chance: usize) -> f64 {
+        let k = thread_rng().gen_range(0, tree.chances[chance].bag.len());
+        let child = match tree.chances[chance].children[k] {
+            Some(child) => child,
+            None => {
+                let node = &tree.chances[chance];
+                let mut game = tree.decisions[0].game.search_clone();
+                game.state = node.board;
+                game.active_piece = node.active_piece;
+                game.next_piece = Piece::new_from_idx(node.bag[k]);
+                let mut remaining = node.bag.clone();
+                remaining.remove(k);
+
+                let child = self.add_decision(tree, game, remaining);
+                tree.chances[chance].children[k] = Some(child);
+                child
+            }
+        };
+        self.simulate_decision(tree, child)
+    }
+
+    // value of the state after a placement which has not been visited before
+    fn evaluate(&self, tree: &Tree, node: usize, edge: usize) -> f64 {
+        let decision = &tree.decisions[node];
+        match self.evaluation {
+            MctsEvaluation::ValueNetwork(agent) => agent.state_value(&decision.edges[edge].features),
+            MctsEvaluation::Rollout(policy, length) => {
+                let mut rng = thread_rng();
+                let mut game = decision.game.search_clone();
+                game.state = game.simulate_placement(&decision.edges[edge].action).unwrap().0;
+                game.active_piece = decision.game.next_piece;
+                let mut bag = decision.remaining.clone();
+
+                let mut value = 0.0;
+                let mut discount = 1.0;
+                for _ in 0..length {
+                    if bag.is_empty() {
+                        bag = (1..=7).collect();
+                    }
+                    game.next_piece = Piece::new_from_idx(bag.remove(rng.gen_range(0, bag.len())));
+
+                    let (possible_actions, rewards, sim_features) = game.get_possible_actions();
+                    let mut best : Option<(usize, f64)> = None;
+                    for (i, features) in sim_features.iter().enumerate() {
+                        let score = policy.evaluate(features);
+                        match best {
+                            Some((_, best_score)) if best_score >= score => (),
+                            _ => best = Some((i, score))
+                        }
+                    }
+                    let i = match best {
+                        Some((i, _)) => i,
+                        None => break
+                    };
+                    value += discount * rewards[i];
+                    discount *= self.discount_factor;
+                    if sim_features[i].is_terminal {
+                        break;
+                    }
+                    game.state = game.simulate_placement(&possible_actions[i]).unwrap().0;
+                    game.active_piece = game.next_piece;
+                }
+                value
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_value_trains_agent() {
+        let mut agent = Agent::new();
+        let game = Game::with_seed(1);
+        let result = Mcts::new(40, MctsEvaluation::ValueNetwork(&agent)).search(&game).unwrap();
+
+        let visits : Vec<u32> = result.action_statistics.iter().map(|(_, visits, _)| *visits).collect();
+        assert_eq!(visits.iter().sum::<u32>(), 40);
+        let most_visited = visits.iter().position(|count| count == visits.iter().max().unwrap()).unwrap();
+        assert_eq!(result.action_statistics[most_visited].0, result.action);
+        // the root value is the visit weighted mean of the placement values
+        let weighted : f64 = result.action_statistics.iter().map(|(_, visits, value)| *visits as f64 * value).sum::<f64>() / 40.0;
+        assert!((result.value - weighted).abs() < 1e-9);
+
+        let features = Features::build(game.state);
+        let before = agent.state_value(&features);
+        let error = agent.train_value(features, result.value);
+        assert!((error - (result.value - before)).abs() < 1e-9);
+        assert!(agent.state_value(&features) != before);
+    }
+}
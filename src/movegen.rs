@@ -0,0 +1,240 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::game;
+
+use game::*;
+
+// Final position of a piece together with the key presses and the single actions leading there from the spawn position.
+// The path moves the piece down with SingularAction::SoftDrop and ends with the hard drop SingularAction::Down
+#[derive(Clone)]
+pub struct Placement {
+    pub piece: Piece,
+    pub keys: Vec<KeyPress>,
+    pub path: Vec<SingularAction>
+}
+
+// Key presses of a human player, holding a key counts as a single press
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyPress {
+    TapLeft,
+    TapRight,
+    DasLeft, // hold until the piece reaches the wall or a block
+    DasRight,
+    RotateClockwise,
+    RotateCounterClockwise,
+    Rotate180,
+    TapSoftDrop, // move down a single row
+    SoftDrop, // hold until the piece reaches the ground
+    HardDrop,
+    Hold
+}
+
+// key presses which move the active piece, in the order in which they are preferred among equally short paths
+const MOVEMENT_KEYS : [KeyPress; 9] = [KeyPress::TapLeft, KeyPress::TapRight, KeyPress::DasLeft, KeyPress::DasRight,
+    KeyPress::RotateClockwise, KeyPress::RotateCounterClockwise, KeyPress::Rotate180, KeyPress::SoftDrop, KeyPress::TapSoftDrop];
+
+// The piece lies within the board if x is in [-3, GAME_WIDTH) and y in [0, GAME_HEIGHT + 3), as its shape is 4 x 4
+const STATE_WIDTH : usize = GAME_WIDTH + 3;
+const STATE_HEIGHT : usize = GAME_HEIGHT + 3;
+const NUM_STATES : usize = STATE_WIDTH * STATE_HEIGHT * 4;
+
+// index of the position and rotation of a valid piece
+fn state_index(piece: &Piece) -> usize {
+    let position = piece.get_position();
+    (((position.0 + 3) as usize * STATE_HEIGHT) + position.1 as usize) * 4 + piece.get_rotation() as usize
+}
+
+fn sorted_cells(piece: &Piece) -> [(i32, i32); 4] {
+    let mut cells = piece.get_cells();
+    cells.sort_unstable();
+    cells
+}
+
+// single action performed (repeatedly) by the movement key
+fn key_action(key: KeyPress) -> SingularAction {
+    match key {
+        KeyPress::TapLeft | KeyPress::DasLeft => SingularAction::Left,
+        KeyPress::TapRight | KeyPress::DasRight => SingularAction::Right,
+        KeyPress::RotateClockwise => SingularAction::Rotate,
+        KeyPress::RotateCounterClockwise => SingularAction::RotateCounterClockwise,
+        KeyPress::Rotate180 => SingularAction::Rotate180,
+        KeyPress::TapSoftDrop | KeyPress::SoftDrop => SingularAction::SoftDrop,
+        KeyPress::HardDrop => SingularAction::Down,
+        KeyPress::Hold => SingularAction::Hold
+    }
+}
+
+// piece after pressing (and possibly holding) the movement key, together with the number of single actions performed
+fn press_key(board: &Board, piece: Piece, key: KeyPress) -> (Piece, usize) {
+    let action = key_action(key);
+    let mut current = piece;
+    let mut count = 0;
+    let mut next = current.simulate_next(action);
+    while board.is_valid_state(next) {
+        current = next;
+        count += 1;
+        match key {
+            KeyPress::DasLeft | KeyPress::DasRight | KeyPress::SoftDrop => next = next.simulate_next(action),
+            _ => break
+        }
+    }
+    (current, count)
+}
+
+// Breadth-first search over the key presses from the spawn position, so every position and rotation the piece can reach
+// with the movement and rotation rules of the game is found with the fewest key presses (finesse). Every reached position
+// is hard dropped, placements occupying the same cells are only returned once with the shortest sequence of key presses
+pub fn generate_placements(board: &Board, piece: Piece) -> Vec<Placement> {
+    let mut placements : Vec<Placement> = Vec::new();
+    if !board.is_valid_state(piece) {
+        return placements;
+    }
+
+    let mut parents : Vec<Option<(usize, KeyPress)>> = vec![None; NUM_STATES];
+    let mut visited = vec![false; NUM_STATES];
+    let mut landed : HashSet<[(i32, i32); 4]> = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited[state_index(&piece)] = true;
+    queue.push_back(piece);
+
+    while let Some(current) = queue.pop_front() {
+        let state = state_index(&current);
+
+        let dropped = board.hard_drop(current);
+        if landed.insert(sorted_cells(&dropped)) {
+            let mut keys = Vec::new();
+            let mut parent = parents[state];
+            while let Some((previous, key)) = parent {
+                keys.push(key);
+                parent = parents[previous];
+            }
+            keys.reverse();
+            // the single actions of each key press are determined by pressing the keys again from the spawn position
+            let mut path = Vec::new();
+            let mut moved = piece;
+            for key in keys.iter() {
+                let (next, count) = press_key(board, moved, *key);
+                moved = next;
+                path.extend(vec![key_action(*key); count]);
+            }
+            keys.push(KeyPress::HardDrop);
+            path.push(SingularAction::Down);
+            placements.push(Placement {
+                piece: dropped,
+                keys,
+                path
+            });
+        }
+
+        for key in MOVEMENT_KEYS.iter() {
+            let next = if *key == KeyPress::SoftDrop { dropped } else { press_key(board, current, *key).0 };
+            if visited[state_index(&next)] {
+                continue;
+            }
+            visited[state_index(&next)] = true;
+            parents[state_index(&next)] = Some((state, *key));
+            queue.push_back(next);
+        }
+    }
+    placements
+}
+
+// The placement with the fewest key presses which locks the piece in the cells of the target piece
+pub fn find_finesse_path(board: &Board, piece: Piece, target: &Piece) -> Option<Placement> {
+    let target_cells = sorted_cells(target);
+    generate_placements(board, piece).into_iter().find(|placement| sorted_cells(&placement.piece) == target_cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // follow the path from the spawn position, returns the locked piece
+    fn follow_path(board: &Board, piece: Piece, path: &[SingularAction]) -> Piece {
+        let mut current = piece;
+        for action in path.iter() {
+            if *action == SingularAction::Down {
+                return board.hard_drop(current);
+            }
+            let next = current.simulate_next(*action);
+            assert!(board.is_valid_state(next), "path moves the piece into a block");
+            current = next;
+        }
+        panic!("path does not end with a hard drop");
+    }
+
+    #[test]
+    fn placements_on_empty_board() {
+        let board = Board::new();
+        for (name, count) in [('T', 34), ('S', 17), ('Z', 17), ('J', 34), ('L', 34), ('I', 17), ('O', 9)].iter() {
+            let piece = Piece::from_name(*name).unwrap();
+            let placements = generate_placements(&board, piece);
+            assert_eq!(placements.len(), *count, "placements of {}", name);
+            for placement in placements.iter() {
+                assert_eq!(sorted_cells(&follow_path(&board, piece, &placement.path)), sorted_cells(&placement.piece));
+            }
+        }
+    }
+
+    #[test]
+    fn tuck_under_overhang() {
+        let board : Board = "XXX.......\n..........".parse().unwrap();
+        let piece = Piece::from_name('I').unwrap();
+        let placements = generate_placements(&board, piece);
+        let tucked = placements.iter()
+            .find(|placement| sorted_cells(&placement.piece) == [(0, 0), (1, 0), (2, 0), (3, 0)])
+            .expect("placement under the overhang");
+        assert!(tucked.path.contains(&SingularAction::SoftDrop));
+        assert_eq!(sorted_cells(&follow_path(&board, piece, &tucked.path)), [(0, 0), (1, 0), (2, 0), (3, 0)]);
+
+        // a hard drop from the top of the board lands on the overhang instead
+        for rotation in 0..4 {
+            for x in -3..GAME_WIDTH as i32 {
+                let above = Piece::new_at(piece.get_type(), (x, GAME_HEIGHT as i32 - 1), rotation);
+                if board.is_valid_state(above) {
+                    assert_ne!(sorted_cells(&board.hard_drop(above)), [(0, 0), (1, 0), (2, 0), (3, 0)]);
+                }
+            }
+        }
+    }
+
+    // number of key presses to lock the piece at the position and rotation (in the cells it occupies after a hard drop)
+    fn finesse_keys(board: &Board, name: char, position: (i32, i32), rotation: i32) -> Vec<KeyPress> {
+        let piece = Piece::from_name(name).unwrap();
+        let target = board.hard_drop(Piece::new_at(piece.get_type(), position, rotation));
+        let placement = find_finesse_path(board, piece, &target).unwrap();
+        assert_eq!(sorted_cells(&follow_path(board, piece, &placement.path)), sorted_cells(&target));
+        placement.keys
+    }
+
+    #[test]
+    fn finesse_key_counts() {
+        let board = Board::new();
+        let top = GAME_HEIGHT as i32 - 1;
+        assert_eq!(finesse_keys(&board, 'T', (3, top), 0), [KeyPress::HardDrop]);
+        assert_eq!(finesse_keys(&board, 'T', (4, top), 0), [KeyPress::TapRight, KeyPress::HardDrop]);
+        assert_eq!(finesse_keys(&board, 'T', (0, top), 0), [KeyPress::DasLeft, KeyPress::HardDrop]);
+        assert_eq!(finesse_keys(&board, 'T', (3, top), 2), [KeyPress::Rotate180, KeyPress::HardDrop]);
+        assert_eq!(finesse_keys(&board, 'T', (7, top), 1).len(), 3); // rotate and hold right
+        assert_eq!(finesse_keys(&board, 'O', (-1, top), 0), [KeyPress::DasLeft, KeyPress::HardDrop]);
+        assert_eq!(finesse_keys(&board, 'I', (-2, top), 1).len(), 3); // rotate and hold left
+        assert_eq!(finesse_keys(&board, 'L', (1, top), 0), [KeyPress::TapLeft, KeyPress::TapLeft, KeyPress::HardDrop]);
+
+        let overhang : Board = "XXX.......\n..........".parse().unwrap();
+        assert_eq!(finesse_keys(&overhang, 'I', (0, 1), 0), [KeyPress::SoftDrop, KeyPress::DasLeft, KeyPress::HardDrop]);
+    }
+
+    #[test]
+    fn finesse_path_with_hold() {
+        let game = Game::with_seed(0);
+        let target = game.state.hard_drop(game.next_piece);
+        let placement = game.find_finesse_path(&target).unwrap();
+        assert_eq!(placement.keys, [KeyPress::Hold, KeyPress::HardDrop]);
+        assert_eq!(placement.path, [SingularAction::Hold, SingularAction::Down]);
+
+        let mut played = game.clone();
+        played.place(&ComposedAction::from_inputs(placement.path));
+        assert_eq!(played.hold_piece.map(|piece| piece.get_type()), Some(game.active_piece.get_type()));
+        assert_eq!(played.state.state, game.state.simulate_board(target).state);
+    }
+}
@@ -1,14 +1,22 @@
+// the layers are written as index based loops over the weight matrices
+#![allow(clippy::needless_range_loop)]
+
 use rand::{Rng};
 const HIDDEN_SIZE : (usize, usize) = (32, 32);
 
 use crate::game;
 use game::FEATURE_LENGTH;
 
+// weights (and gradients) of the layers
+type InputWeights = [[f64; FEATURE_LENGTH]; HIDDEN_SIZE.0];
+type HiddenWeights = [[f64; HIDDEN_SIZE.0]; HIDDEN_SIZE.1];
+type OutputWeights = [[f64; HIDDEN_SIZE.1]; 1];
+
 #[derive(Clone, Copy)]
 pub struct Network {
-    w1: [[f64; FEATURE_LENGTH]; HIDDEN_SIZE.0],
-    w2: [[f64; HIDDEN_SIZE.0]; HIDDEN_SIZE.1],
-    w3: [[f64; HIDDEN_SIZE.1]; 1],
+    w1: InputWeights,
+    w2: HiddenWeights,
+    w3: OutputWeights,
     // intermediate results of the previous forward pass
     previous_hidden1: [f64; HIDDEN_SIZE.0],
     previous_hidden2: [f64; HIDDEN_SIZE.1],
@@ -17,6 +25,12 @@ pub struct Network {
 // Simple Fully-Connected Neural Network
 // w3 * relu ( w2 * relu ( w1 * inp ) )
 
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Network {
     pub fn new() -> Network {
         let mut w1 = [[0.0; FEATURE_LENGTH]; HIDDEN_SIZE.0];
@@ -98,7 +112,7 @@ impl Network {
 
     // Backward pass, return gradients for each weight
     pub fn backward(&self, error_gradient: f64, features: [f64; FEATURE_LENGTH]) 
-            -> ([[f64; FEATURE_LENGTH]; HIDDEN_SIZE.0], [[f64; HIDDEN_SIZE.0]; HIDDEN_SIZE.1], [[f64; HIDDEN_SIZE.1]; 1]) {
+            -> (InputWeights, HiddenWeights, OutputWeights) {
         let mut w1_gradient = [[0.0; FEATURE_LENGTH]; HIDDEN_SIZE.0];
         let mut w2_gradient = [[0.0; HIDDEN_SIZE.0]; HIDDEN_SIZE.1];
         let mut w3_gradient = [[0.0; HIDDEN_SIZE.1]; 1];
@@ -164,9 +178,9 @@ impl Network {
 
     // perform gradient ascent
     pub fn update_parameters(&mut self, scalar: f64, 
-        w1_gradient: [[f64; FEATURE_LENGTH]; HIDDEN_SIZE.0], 
-        w2_gradient: [[f64; HIDDEN_SIZE.0]; HIDDEN_SIZE.1], 
-        w3_gradient: [[f64; HIDDEN_SIZE.1]; 1]) {
+        w1_gradient: InputWeights, 
+        w2_gradient: HiddenWeights, 
+        w3_gradient: OutputWeights) {
         for j in 0..HIDDEN_SIZE.0 {
             for i in 0..FEATURE_LENGTH {
                 self.w1[j][i] += scalar * w1_gradient[j][i];
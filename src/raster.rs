@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+use gif::SetParameter;
+
+use crate::game;
+use crate::font;
+
+use game::*;
+
+// Software rendering of a game into an RGBA image, usable without a window (e.g. on servers without a display)
+const CELL_SIZE : usize = 16;
+const MARGIN : usize = 8;
+const TEXT_SCALE : usize = 2;
+const LINE_HEIGHT : usize = (font::GLYPH_HEIGHT + 2) * TEXT_SCALE;
+const PREVIEW_WIDTH : usize = 4 * CELL_SIZE;
+const PANEL_WIDTH : usize = 2 * PREVIEW_WIDTH + MARGIN;
+
+pub const IMAGE_WIDTH : usize = 3 * MARGIN + RENDER_WIDTH * CELL_SIZE + PANEL_WIDTH;
+pub const IMAGE_HEIGHT : usize = 2 * MARGIN + RENDER_HEIGHT * CELL_SIZE;
+
+const BACKGROUND : [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const BORDER_COLOR : [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const TEXT_COLOR : [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const GHOST_ALPHA : f32 = 0.3;
+
+// RGBA image, rows from top to bottom
+#[derive(Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, color: [f32; 4]) -> Image {
+        let mut image = Image {
+            width,
+            height,
+            pixels: vec![0; width * height * 4]
+        };
+        image.fill_rect(0, 0, width, height, color);
+        image
+    }
+
+    // fill the rectangle with the color, blending it with the image according to its alpha value
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [f32; 4]) {
+        let alpha = color[3].clamp(0.0, 1.0);
+        for j in y..(y + height).min(self.height) {
+            for i in x..(x + width).min(self.width) {
+                let offset = (j * self.width + i) * 4;
+                for (k, channel) in color.iter().take(3).enumerate() {
+                    let previous = self.pixels[offset + k] as f32 / 255.0;
+                    let value = alpha * channel + (1.0 - alpha) * previous;
+                    self.pixels[offset + k] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+                self.pixels[offset + 3] = 255;
+            }
+        }
+    }
+
+    pub fn draw_border(&mut self, x: usize, y: usize, width: usize, height: usize, color: [f32; 4]) {
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, y + height - 1, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(x + width - 1, y, 1, height, color);
+    }
+
+    // text in the bitmap font with its top left corner at (x, y)
+    pub fn draw_text(&mut self, text: &str, x: usize, y: usize, scale: usize, color: [f32; 4]) {
+        for (i, j) in font::text_pixels(text) {
+            self.fill_rect(x + i * scale, y + j * scale, scale, scale, color);
+        }
+    }
+
+    pub fn save_png(&self, path: &str) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+// cell (x, y) of the visible board, y from bottom to top
+fn fill_cell(image: &mut Image, x: i32, y: i32, color: [f32; 4]) {
+    if x < 0 || x >= RENDER_WIDTH as i32 || y < 0 || y >= RENDER_HEIGHT as i32 {
+        return;
+    }
+    let left = MARGIN + x as usize * CELL_SIZE;
+    let top = MARGIN + (RENDER_HEIGHT - 1 - y as usize) * CELL_SIZE;
+    image.fill_rect(left + 1, top + 1, CELL_SIZE - 1, CELL_SIZE - 1, color);
+}
+
+// piece in its spawn rotation with the top left corner of its shape at (x, y)
+fn draw_preview(image: &mut Image, piece: &Piece, x: usize, y: usize) {
+    for j in 0..4 {
+        for i in 0..4 {
+            if piece.shape[0][j][i] != 0 {
+                image.fill_rect(x + i * CELL_SIZE + 1, y + j * CELL_SIZE + 1, CELL_SIZE - 1, CELL_SIZE - 1, piece.color);
+            }
+        }
+    }
+}
+
+// Board with the active piece and its ghost, the next and the held piece
+pub fn render(board: &Board, active: Piece, next: Piece, hold: Option<Piece>) -> Image {
+    let mut image = Image::new(IMAGE_WIDTH, IMAGE_HEIGHT, BACKGROUND);
+    image.draw_border(MARGIN - 1, MARGIN - 1, RENDER_WIDTH * CELL_SIZE + 2, RENDER_HEIGHT * CELL_SIZE + 2, BORDER_COLOR);
+
+    let mut ghost_color = active.color;
+    ghost_color[3] = GHOST_ALPHA;
+    let ghost = if board.is_valid_state(active) { board.hard_drop(active) } else { active };
+    for (x, y) in ghost.get_cells().iter() {
+        fill_cell(&mut image, *x, *y, ghost_color);
+    }
+    let board = board.simulate_board(active);
+    for y in 0..RENDER_HEIGHT {
+        for x in 0..RENDER_WIDTH {
+            if board.state[y][x] != 0 {
+                fill_cell(&mut image, x as i32, y as i32, get_cell_color(board.state[y][x]));
+            }
+        }
+    }
+
+    let left = 2 * MARGIN + RENDER_WIDTH * CELL_SIZE;
+    image.draw_text("NEXT", left, MARGIN, TEXT_SCALE, TEXT_COLOR);
+    draw_preview(&mut image, &next, left, MARGIN + LINE_HEIGHT);
+    image.draw_text("HOLD", left + PREVIEW_WIDTH + MARGIN, MARGIN, TEXT_SCALE, TEXT_COLOR);
+    if let Some(hold_piece) = hold {
+        draw_preview(&mut image, &hold_piece, left + PREVIEW_WIDTH + MARGIN, MARGIN + LINE_HEIGHT);
+    }
+    image
+}
+
+// The pieces of the game as above, with the statistics of the game below the previews
+pub fn render_game(game: &Game) -> Image {
+    let mut image = render(&game.state, game.active_piece, game.next_piece, game.hold_piece);
+    let lines = [
+        format!("SCORE {}", game.score),
+        format!("LEVEL {}", game.get_level()),
+        format!("LINES {}", game.lines_cleared),
+        format!("PIECES {}", game.pieces_placed)
+    ];
+    let left = 2 * MARGIN + RENDER_WIDTH * CELL_SIZE;
+    let mut y = MARGIN + 2 * LINE_HEIGHT + 2 * CELL_SIZE;
+    for line in lines.iter() {
+        image.draw_text(line, left, y, TEXT_SCALE, TEXT_COLOR);
+        y += LINE_HEIGHT;
+    }
+    image
+}
+
+// Animated GIF written frame by frame, the file is completed when the recorder is dropped
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    delay: u16 // display time of each frame in hundredths of a second
+}
+
+impl GifRecorder {
+    pub fn new(path: &str, delay: u16) -> io::Result<GifRecorder> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, IMAGE_WIDTH as u16, IMAGE_HEIGHT as u16, &[])?;
+        encoder.set(gif::Repeat::Infinite)?;
+        Ok(GifRecorder {
+            encoder,
+            delay
+        })
+    }
+
+    pub fn add_frame(&mut self, image: &Image) -> io::Result<()> {
+        let mut pixels = image.pixels.clone();
+        let mut frame = gif::Frame::from_rgba_speed(image.width as u16, image.height as u16, &mut pixels, 10);
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // color of the center of cell (x, y) of the visible board
+    fn cell_color(image: &Image, x: usize, y: usize) -> &[u8] {
+        let i = MARGIN + x * CELL_SIZE + CELL_SIZE / 2;
+        let j = MARGIN + (RENDER_HEIGHT - 1 - y) * CELL_SIZE + CELL_SIZE / 2;
+        let offset = (j * image.width + i) * 4;
+        &image.pixels[offset..offset + 4]
+    }
+
+    #[test]
+    fn render_board_with_pieces() {
+        let board : Board = "XXXX..XXXX".parse().unwrap();
+        let active = Piece::new_at(1, (3, 5), 0);
+        let image = render(&board, active, Piece::new_from_idx(2), None);
+        assert_eq!((image.width, image.height, image.pixels.len()), (IMAGE_WIDTH, IMAGE_HEIGHT, IMAGE_WIDTH * IMAGE_HEIGHT * 4));
+
+        assert_eq!(cell_color(&image, 0, 0), &[128, 128, 128, 255][..]);
+        assert_eq!(cell_color(&image, 4, 0), &[0, 0, 0, 255][..]);
+        let (x, y) = active.get_cells()[0];
+        let color = get_cell_color(1);
+        let expected : Vec<u8> = color.iter().take(3).map(|channel| (channel * 255.0).round() as u8).chain(Some(255)).collect();
+        assert_eq!(cell_color(&image, x as usize, y as usize), &expected[..]);
+        // the ghost is blended with the background where a hard drop would lock the piece
+        let (x, y) = board.hard_drop(active).get_cells()[0];
+        assert_ne!(cell_color(&image, x as usize, y as usize), &[0, 0, 0, 255][..]);
+        assert_ne!(cell_color(&image, x as usize, y as usize), &expected[..]);
+    }
+
+    #[test]
+    fn render_game_adds_statistics() {
+        let game = Game::with_seed(0);
+        let board = render(&game.state, game.active_piece, game.next_piece, game.hold_piece);
+        let full = render_game(&game);
+        assert_ne!(board.pixels, full.pixels);
+        // the statistics are only drawn in the panel next to the board
+        let left = 2 * MARGIN + RENDER_WIDTH * CELL_SIZE;
+        for j in 0..IMAGE_HEIGHT {
+            let row = j * IMAGE_WIDTH * 4;
+            assert_eq!(board.pixels[row..row + left * 4], full.pixels[row..row + left * 4]);
+        }
+    }
+}
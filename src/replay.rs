@@ -0,0 +1,326 @@
+use std::fs;
+use std::io;
+
+use crate::game;
+use crate::timing;
+
+use game::*;
+use timing::TimingConfig;
+
+// first line of a replay file
+const REPLAY_HEADER : &str = "tetris-replay 1";
+
+// character of each single action in the input line of a replay file
+const ACTION_CODES : [(char, SingularAction); 9] = [
+    ('g', SingularAction::None),
+    ('L', SingularAction::Left),
+    ('R', SingularAction::Right),
+    ('C', SingularAction::Rotate),
+    ('W', SingularAction::RotateCounterClockwise),
+    ('F', SingularAction::Rotate180),
+    ('s', SingularAction::SoftDrop),
+    ('D', SingularAction::Down),
+    ('H', SingularAction::Hold)
+];
+const TICK_CODE : char = '.';
+const SOFT_DROP_TICK_CODE : char = ',';
+
+// Input of a recorded game: either a single action (Game::step) or a frame (Game::tick)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReplayInput {
+    Step(SingularAction),
+    Tick(bool) // soft drop was held during the frame
+}
+
+impl ReplayInput {
+    fn code(&self) -> char {
+        match self {
+            ReplayInput::Step(action) => ACTION_CODES.iter().find(|(_, code_action)| code_action == action).map(|(code, _)| *code).unwrap_or('?'),
+            ReplayInput::Tick(false) => TICK_CODE,
+            ReplayInput::Tick(true) => SOFT_DROP_TICK_CODE
+        }
+    }
+
+    fn from_code(code: char) -> Option<ReplayInput> {
+        match code {
+            TICK_CODE => Some(ReplayInput::Tick(false)),
+            SOFT_DROP_TICK_CODE => Some(ReplayInput::Tick(true)),
+            _ => ACTION_CODES.iter().find(|(action_code, _)| *action_code == code).map(|(_, action)| ReplayInput::Step(*action))
+        }
+    }
+
+    // apply the input to the game
+    pub fn apply(&self, game: &mut Game) -> StepResult {
+        match self {
+            ReplayInput::Step(action) => game.step(*action),
+            ReplayInput::Tick(soft_drop) => game.tick(*soft_drop)
+        }
+    }
+}
+
+// Everything needed to simulate a game again: the seed of the piece sequence, the position the game started from,
+// the timing rules and every input, together with the final result of the game to verify the simulation
+#[derive(Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub start_position: Option<StartPosition>, // None for games started from an empty board
+    pub timing: TimingConfig,
+    pub inputs: Vec<(ReplayInput, u32)>, // runs of the same input
+    pub score: i32,
+    pub lines: i32,
+    pub pieces: i32
+}
+
+// Final result of a replay which was simulated again
+pub struct Verification {
+    pub game: Game,
+    pub matches: bool // the simulated game has the recorded score, lines and pieces
+}
+
+impl Replay {
+    pub fn new(seed: u64, timing: TimingConfig) -> Replay {
+        Replay {
+            seed,
+            start_position: None,
+            timing,
+            inputs: Vec::new(),
+            score: 0,
+            lines: 0,
+            pieces: 0
+        }
+    }
+
+    pub fn record(&mut self, input: ReplayInput) {
+        if let Some((last, count)) = self.inputs.last_mut() {
+            if *last == input {
+                *count += 1;
+                return;
+            }
+        }
+        self.inputs.push((input, 1));
+    }
+
+    // store the final result of the game
+    pub fn finish(&mut self, game: &Game) {
+        self.score = game.score;
+        self.lines = game.lines_cleared;
+        self.pieces = game.pieces_placed;
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.iter().map(|(_, count)| *count as usize).sum()
+    }
+
+    // game in the state the recording started from, fails if the start position is not valid
+    pub fn new_game(&self) -> io::Result<Game> {
+        let mut game = match &self.start_position {
+            Some(start) => Game::from_position(start.board, start.active_piece, &start.queue, start.hold_piece, self.seed)?,
+            None => Game::with_seed(self.seed)
+        };
+        game.timing = self.timing;
+        Ok(game)
+    }
+
+    // Simulate the game again through Game::step and Game::tick until the inputs end or the game is over
+    pub fn verify(&self) -> io::Result<Verification> {
+        let mut playback = Playback::new(self)?;
+        while let Some(result) = playback.advance() {
+            if let StepResult::GameOver = result {
+                break;
+            }
+        }
+        let matches = playback.matches_result();
+        Ok(Verification {
+            game: playback.game,
+            matches
+        })
+    }
+
+    // Text format: the header, a line for each setting and a single line with the inputs,
+    // where each input is a character optionally preceded by the length of its run, e.g. "12.LLD".
+    // Games from a start position have a line with its board (rows separated by '/'), active piece, queue and hold, e.g.
+    // "position XXXX....../XXX...XXXX T 3 2 2 SZ -"
+    pub fn to_text(&self) -> String {
+        let gravity = self.timing.gravity_override.map(|gravity| gravity.to_string()).unwrap_or_else(|| "-".to_string());
+        let mut inputs = String::new();
+        for (input, count) in self.inputs.iter() {
+            if *count > 1 {
+                inputs += &count.to_string();
+            }
+            inputs.push(input.code());
+        }
+        let position = match &self.start_position {
+            Some(start) => {
+                let (x, y) = start.active_piece.get_position();
+                let queue : String = start.queue.iter().map(|piece| piece.get_name()).collect();
+                format!("position {} {} {} {} {} {} {}\n", start.board.to_string().replace('\n', "/"), start.active_piece.get_name(),
+                    x, y, start.active_piece.get_rotation(), if queue.is_empty() { "-" } else { &queue },
+                    start.hold_piece.map(|piece| piece.get_name()).unwrap_or("-"))
+            },
+            None => String::new()
+        };
+        format!("{}\nseed {}\n{}timing {} {} {} {}\nresult {} {} {}\ninputs {}\n", REPLAY_HEADER, self.seed, position,
+            self.timing.lock_delay, self.timing.move_reset_limit, self.timing.soft_drop_factor, gravity,
+            self.score, self.lines, self.pieces, inputs)
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Replay> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = text.lines();
+        if lines.next().map(|line| line.trim()) != Some(REPLAY_HEADER) {
+            return Err(invalid("not a replay file".to_string()));
+        }
+
+        let mut replay = Replay::new(0, TimingConfig::new());
+        for line in lines {
+            let mut parts = line.split_whitespace();
+            let key = match parts.next() {
+                Some(key) => key,
+                None => continue
+            };
+            let values : Vec<&str> = parts.collect();
+            let parse_error = || invalid(format!("invalid line: {}", line));
+            match (key, values.len()) {
+                ("seed", 1) => replay.seed = values[0].parse().map_err(|_| parse_error())?,
+                ("position", 7) => {
+                    let piece = |name: &str| {
+                        let mut chars = name.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(name), None) => Piece::from_name(name).ok_or_else(parse_error),
+                            _ => Err(parse_error())
+                        }
+                    };
+                    let active_type = piece(values[1])?.get_type();
+                    let x = values[2].parse().map_err(|_| parse_error())?;
+                    let y = values[3].parse().map_err(|_| parse_error())?;
+                    let rotation = values[4].parse().map_err(|_| parse_error())?;
+                    let queue = if values[5] == "-" { Vec::new() } else {
+                        values[5].chars().map(|name| Piece::from_name(name).ok_or_else(parse_error)).collect::<io::Result<Vec<Piece>>>()?
+                    };
+                    replay.start_position = Some(StartPosition {
+                        board: values[0].parse()?,
+                        active_piece: Piece::new_at(active_type, (x, y), rotation),
+                        queue,
+                        hold_piece: if values[6] == "-" { None } else { Some(piece(values[6])?) }
+                    });
+                },
+                ("timing", 4) => {
+                    replay.timing.lock_delay = values[0].parse().map_err(|_| parse_error())?;
+                    replay.timing.move_reset_limit = values[1].parse().map_err(|_| parse_error())?;
+                    replay.timing.soft_drop_factor = values[2].parse().map_err(|_| parse_error())?;
+                    replay.timing.gravity_override = if values[3] == "-" { None } else { Some(values[3].parse().map_err(|_| parse_error())?) };
+                },
+                ("result", 3) => {
+                    replay.score = values[0].parse().map_err(|_| parse_error())?;
+                    replay.lines = values[1].parse().map_err(|_| parse_error())?;
+                    replay.pieces = values[2].parse().map_err(|_| parse_error())?;
+                },
+                ("inputs", 0) => (),
+                ("inputs", 1) => {
+                    let mut count : Option<u32> = None;
+                    for character in values[0].chars() {
+                        if let Some(digit) = character.to_digit(10) {
+                            count = Some(count.unwrap_or(0).checked_mul(10).and_then(|count| count.checked_add(digit))
+                                .ok_or_else(|| invalid("input repeated too often".to_string()))?);
+                            continue;
+                        }
+                        let input = ReplayInput::from_code(character).ok_or_else(|| invalid(format!("unknown input: {}", character)))?;
+                        let count = count.take().unwrap_or(1);
+                        if count == 0 {
+                            return Err(invalid(format!("input repeated 0 times: {}", character)));
+                        }
+                        match replay.inputs.last_mut() {
+                            Some((last, run)) if *last == input => {
+                                *run = run.checked_add(count).ok_or_else(|| invalid("input repeated too often".to_string()))?;
+                            },
+                            _ => replay.inputs.push((input, count))
+                        }
+                    }
+                    if count.is_some() {
+                        return Err(parse_error());
+                    }
+                },
+                _ => return Err(parse_error())
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &str) -> io::Result<Replay> {
+        Replay::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+// Step by step simulation of a replay, e.g. to watch it
+pub struct Playback<'a> {
+    replay: &'a Replay,
+    pub game: Game,
+    run: usize, // position in the runs of inputs
+    repeat: u32 // inputs of the current run already applied
+}
+
+impl<'a> Playback<'a> {
+    pub fn new(replay: &'a Replay) -> io::Result<Playback<'a>> {
+        Ok(Playback {
+            replay,
+            game: replay.new_game()?,
+            run: 0,
+            repeat: 0
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.run >= self.replay.inputs.len()
+    }
+
+    // the game has the recorded score, lines and pieces
+    pub fn matches_result(&self) -> bool {
+        self.game.score == self.replay.score && self.game.lines_cleared == self.replay.lines && self.game.pieces_placed == self.replay.pieces
+    }
+
+    // the next input which will be applied
+    pub fn peek(&self) -> Option<ReplayInput> {
+        self.replay.inputs.get(self.run).map(|(input, _)| *input)
+    }
+
+    // apply the next input, None once all inputs have been applied
+    pub fn advance(&mut self) -> Option<StepResult> {
+        let (input, count) = *self.replay.inputs.get(self.run)?;
+        self.repeat += 1;
+        if self.repeat >= count {
+            self.run += 1;
+            self.repeat = 0;
+        }
+        Some(input.apply(&mut self.game))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_inputs(inputs: &str) -> String {
+        format!("{}\nseed 5\ninputs {}\n", REPLAY_HEADER, inputs)
+    }
+
+    #[test]
+    fn input_runs() {
+        let replay = Replay::from_text(&with_inputs("12.LL3D,")).unwrap();
+        let expected = [(ReplayInput::Tick(false), 12), (ReplayInput::Step(SingularAction::Left), 2),
+                        (ReplayInput::Step(SingularAction::Down), 3), (ReplayInput::Tick(true), 1)];
+        assert_eq!(replay.inputs, expected);
+        assert_eq!(Replay::from_text(&replay.to_text()).unwrap().inputs, expected);
+    }
+
+    #[test]
+    fn invalid_input_runs() {
+        for inputs in ["0L", "99999999999L", "4294967295L4294967295L", "3L2", "x"].iter() {
+            let error = Replay::from_text(&with_inputs(inputs)).err().unwrap_or_else(|| panic!("{} should not parse", inputs));
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::game;
+
+use game::*;
+
+// points per cell the piece is moved down by the player
+pub const SOFT_DROP_POINTS : i32 = 1;
+pub const HARD_DROP_POINTS : i32 = 2;
+
+// index of the T piece in the templates
+const T_PIECE : u8 = 7;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Spin {
+    None,
+    TSpinMini,
+    TSpin
+}
+
+// Scoring of a single locked piece
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LockEvent {
+    pub piece_type: u8,
+    pub lines: i32,
+    pub spin: Spin,
+    pub combo: i32, // number of consecutive line clears before this one, -1 if no lines were cleared
+    pub back_to_back: bool, // the back-to-back bonus has been awarded
+    pub perfect_clear: bool,
+    pub score: i32 // points for the lock, without the drop points
+}
+
+// State carried between locks: the combo counter and whether the last line clear was a difficult one
+#[derive(Clone, Copy)]
+pub struct Scoring {
+    pub combo: i32,
+    pub back_to_back: bool
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// points of a line clear at level 1 by the number of lines
+fn line_clear_points(lines: i32, spin: Spin) -> i32 {
+    match (spin, lines) {
+        (Spin::None, 1) => 100,
+        (Spin::None, 2) => 300,
+        (Spin::None, 3) => 500,
+        (Spin::None, 4) => 800,
+        (Spin::TSpinMini, 0) => 100,
+        (Spin::TSpinMini, 1) => 200,
+        (Spin::TSpinMini, 2) => 400,
+        (Spin::TSpin, 0) => 400,
+        (Spin::TSpin, 1) => 800,
+        (Spin::TSpin, 2) => 1200,
+        (Spin::TSpin, 3) => 1600,
+        _ => 0
+    }
+}
+
+fn perfect_clear_points(lines: i32, back_to_back: bool) -> i32 {
+    match lines {
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        4 if back_to_back => 3200,
+        4 => 2000,
+        _ => 0
+    }
+}
+
+impl Scoring {
+    pub fn new() -> Scoring {
+        Scoring {
+            combo: -1,
+            back_to_back: false
+        }
+    }
+
+    // Score a locked piece at the given level and update the combo and back-to-back state
+    pub fn lock(&mut self, piece_type: u8, lines: i32, spin: Spin, perfect_clear: bool, level: i32) -> LockEvent {
+        let difficult = lines == 4 || (lines > 0 && spin != Spin::None);
+        let back_to_back = difficult && self.back_to_back;
+
+        let mut score = line_clear_points(lines, spin);
+        if back_to_back {
+            score += score / 2;
+        }
+        if lines > 0 {
+            self.combo += 1;
+            self.back_to_back = difficult;
+            score += 50 * self.combo;
+        } else {
+            self.combo = -1;
+        }
+        if perfect_clear {
+            score += perfect_clear_points(lines, back_to_back);
+        }
+
+        LockEvent {
+            piece_type,
+            lines,
+            spin,
+            combo: self.combo,
+            back_to_back,
+            perfect_clear,
+            score: score * level
+        }
+    }
+}
+
+fn is_occupied(board: &Board, x: i32, y: i32) -> bool {
+    x < 0 || x >= GAME_WIDTH as i32 || y < 0 || y >= GAME_HEIGHT as i32 || board.state[y as usize][x as usize] != 0
+}
+
+// 3-corner rule: a T piece whose last successful move was a rotation is spun in if at least three of the
+// corners around its center are occupied (walls and floor count as occupied). It is a full T-spin if both
+// corners on the side it points to are occupied, a mini otherwise
+pub fn detect_spin(board: &Board, piece: &Piece, rotated_last: bool) -> Spin {
+    if !rotated_last || piece.get_type() != T_PIECE {
+        return Spin::None;
+    }
+    // the center of the T is at (1, 1) in all rotations of the template
+    let position = piece.get_position();
+    let (x, y) = (position.0 + 1, position.1 - 1);
+    let top_left = is_occupied(board, x - 1, y + 1);
+    let top_right = is_occupied(board, x + 1, y + 1);
+    let bottom_left = is_occupied(board, x - 1, y - 1);
+    let bottom_right = is_occupied(board, x + 1, y - 1);
+
+    let corners = [top_left, top_right, bottom_left, bottom_right].iter().filter(|corner| **corner).count();
+    if corners < 3 {
+        return Spin::None;
+    }
+    let front = match piece.get_rotation() {
+        0 => top_left && top_right, // pointing up
+        1 => top_right && bottom_right,
+        2 => bottom_left && bottom_right,
+        _ => top_left && bottom_left
+    };
+    if front { Spin::TSpin } else { Spin::TSpinMini }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // score of a single lock without previous line clears
+    fn first_lock(lines: i32, spin: Spin, level: i32) -> i32 {
+        Scoring::new().lock(T_PIECE, lines, spin, false, level).score
+    }
+
+    #[test]
+    fn line_clears() {
+        let expected = [(1, 100), (2, 300), (3, 500), (4, 800)];
+        for (lines, points) in expected.iter() {
+            assert_eq!(first_lock(*lines, Spin::None, 1), *points);
+            assert_eq!(first_lock(*lines, Spin::None, 3), 3 * points);
+        }
+        assert_eq!(first_lock(0, Spin::None, 1), 0);
+    }
+
+    #[test]
+    fn t_spins() {
+        let expected = [(Spin::TSpinMini, 0, 100), (Spin::TSpinMini, 1, 200), (Spin::TSpinMini, 2, 400),
+                        (Spin::TSpin, 0, 400), (Spin::TSpin, 1, 800), (Spin::TSpin, 2, 1200), (Spin::TSpin, 3, 1600)];
+        for (spin, lines, points) in expected.iter() {
+            assert_eq!(first_lock(*lines, *spin, 1), *points);
+            assert_eq!(first_lock(*lines, *spin, 2), 2 * points);
+        }
+    }
+
+    #[test]
+    fn back_to_back() {
+        let mut scoring = Scoring::new();
+        assert!(!scoring.lock(5, 4, Spin::None, false, 1).back_to_back);
+        // a lock without line clears keeps the back-to-back state but ends the combo
+        assert_eq!(scoring.lock(5, 0, Spin::None, false, 1).score, 0);
+        let tetris = scoring.lock(5, 4, Spin::None, false, 1);
+        assert!(tetris.back_to_back);
+        assert_eq!(tetris.score, 1200);
+        let t_spin = scoring.lock(T_PIECE, 2, Spin::TSpin, false, 1);
+        assert!(t_spin.back_to_back);
+        assert_eq!(t_spin.score, 1800 + 50);
+
+        // a single breaks the chain
+        assert!(!scoring.lock(1, 1, Spin::None, false, 1).back_to_back);
+        let tetris = scoring.lock(5, 4, Spin::None, false, 1);
+        assert!(!tetris.back_to_back);
+        assert_eq!(tetris.score, 800 + 50 * 3);
+    }
+
+    #[test]
+    fn combo() {
+        let mut scoring = Scoring::new();
+        let scores : Vec<i32> = (0..4).map(|_| scoring.lock(1, 1, Spin::None, false, 1).score).collect();
+        assert_eq!(scores, [100, 150, 200, 250]);
+        assert_eq!(scoring.combo, 3);
+        let miss = scoring.lock(1, 0, Spin::None, false, 1);
+        assert_eq!((miss.combo, miss.score), (-1, 0));
+        assert_eq!(scoring.lock(1, 1, Spin::None, false, 2).score, 200);
+    }
+
+    #[test]
+    fn perfect_clear() {
+        let lock = Scoring::new().lock(5, 4, Spin::None, true, 1);
+        assert!(lock.perfect_clear);
+        assert_eq!(lock.score, 800 + 2000);
+        assert_eq!(Scoring::new().lock(1, 1, Spin::None, true, 2).score, 2 * (100 + 800));
+    }
+
+    #[test]
+    fn three_corner_rule() {
+        // T pointing down into a slot with both corners below and one above it occupied
+        let board : Board = "XXXX......\nXXX...XXXX\nXXXX.XXXXX".parse().unwrap();
+        let slot = Piece::new_at(T_PIECE, (3, 2), 2);
+        assert!(board.is_valid_state(slot));
+        assert_eq!(detect_spin(&board, &slot, true), Spin::TSpin);
+        assert_eq!(detect_spin(&board, &slot, false), Spin::None);
+
+        // T pointing up next to a block: both corners below (the floor) but only one corner in front of it
+        let board : Board = "X.........\n..........".parse().unwrap();
+        let mini = Piece::new_at(T_PIECE, (0, 1), 0);
+        assert!(board.is_valid_state(mini));
+        assert_eq!(detect_spin(&board, &mini, true), Spin::TSpinMini);
+
+        // only the two corners on the floor are occupied
+        let flat = Piece::new_at(T_PIECE, (3, 1), 0);
+        assert_eq!(detect_spin(&Board::new(), &flat, true), Spin::None);
+        // other pieces never spin
+        let board : Board = "XXXX......\nXXX...XXXX\nXXXX.XXXXX".parse().unwrap();
+        assert_eq!(detect_spin(&board, &Piece::new_at(3, (3, 2), 2), true), Spin::None);
+    }
+}
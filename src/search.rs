@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+
+use crate::game;
+use crate::agent;
+use crate::linear;
+
+use game::*;
+use agent::Agent;
+use linear::LinearEvaluator;
+
+// Pieces whose type is known when selecting an action: the active piece and the next piece
+pub const KNOWN_PIECES : usize = 2;
+
+// Evaluation of the game state at the end of a sequence of placements
+#[derive(Clone, Copy)]
+pub enum LeafEvaluation<'a> {
+    // linear evaluation of the final state, counting the lines cleared along the whole sequence
+    Heuristic(LinearEvaluator),
+    // discounted rewards along the sequence plus the discounted value of the final state
+    ValueNetwork(&'a Agent)
+}
+
+impl<'a> LeafEvaluation<'a> {
+    pub fn discount_factor(&self) -> f64 {
+        match self {
+            LeafEvaluation::Heuristic(_) => 1.0,
+            LeafEvaluation::ValueNetwork(agent) => agent.get_discount_factor()
+        }
+    }
+
+    // score of a sequence: `reward` is the discounted sum of rewards, `discount` the discount of the final state
+    pub fn score(&self, reward: f64, discount: f64, lines: i32, leaf: &Features) -> f64 {
+        match self {
+            LeafEvaluation::Heuristic(evaluator) => {
+                let mut features = *leaf;
+                features.lines_cleared = lines;
+                evaluator.evaluate(&features)
+            },
+            LeafEvaluation::ValueNetwork(agent) => {
+                if leaf.is_terminal {
+                    reward
+                } else {
+                    reward + discount * agent.state_value(leaf)
+                }
+            }
+        }
+    }
+}
+
+// Sequence of placements explored by the beam search
+#[derive(Clone)]
+struct Node {
+    game: Game, // game before the last placement of the sequence
+    last_action: ComposedAction,
+    first_action: ComposedAction,
+    reward: f64,
+    discount: f64,
+    lines: i32,
+    features: Features,
+    score: f64
+}
+
+// Beam search over the placements of the known pieces, keeping the best `width` sequences at each depth
+pub struct BeamSearch<'a> {
+    pub width: usize,
+    pub depth: usize,
+    pub evaluation: LeafEvaluation<'a>
+}
+
+impl<'a> BeamSearch<'a> {
+    pub fn new(width: usize, depth: usize, evaluation: LeafEvaluation<'a>) -> BeamSearch<'a> {
+        BeamSearch {
+            width,
+            depth,
+            evaluation
+        }
+    }
+
+    fn expand(&self, game: &Game, parent: Option<&Node>, children: &mut Vec<Node>) {
+        let gamma = self.evaluation.discount_factor();
+        let (possible_actions, rewards, sim_features) = game.get_possible_actions();
+        for (i, action) in possible_actions.iter().enumerate() {
+            let (first_action, reward, discount, lines) = match parent {
+                Some(node) => (node.first_action.clone(), node.reward, node.discount, node.lines),
+                None => (action.clone(), 0.0, 1.0, 0)
+            };
+            let reward = reward + discount * rewards[i];
+            let lines = lines + sim_features[i].lines_cleared;
+            let discount = discount * gamma;
+            children.push(Node {
+                game: game.search_clone(),
+                last_action: action.clone(),
+                first_action,
+                reward,
+                discount,
+                lines,
+                features: sim_features[i],
+                score: self.evaluation.score(reward, discount, lines, &sim_features[i])
+            });
+        }
+    }
+
+    pub fn select_action(&self, game: &Game) -> Option<ComposedAction> {
+        // deeper levels would need pieces which are not known yet
+        let depth = self.depth.clamp(1, KNOWN_PIECES);
+
+        let mut beam : Vec<Node> = Vec::new();
+        self.expand(game, None, &mut beam);
+        for _ in 1..depth {
+            beam.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+            beam.truncate(self.width);
+
+            let mut children = Vec::new();
+            for node in beam.iter() {
+                if node.features.is_terminal {
+                    children.push(node.clone());
+                    continue;
+                }
+                let mut next_game = node.game.search_clone();
+                next_game.place(&node.last_action);
+                self.expand(&next_game, Some(node), &mut children);
+            }
+            beam = children;
+        }
+
+        beam.iter()
+            .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal))
+            .map(|node| node.first_action.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expectimax::Expectimax;
+    use crate::linear::LinearEvaluator;
+
+    #[test]
+    fn beam_search_matches_exhaustive_search() {
+        let evaluator = LinearEvaluator::new([-0.5, -0.2, -3.0, -0.3, -0.2, 1.0, -10.0]);
+        let greedy = BeamSearch::new(1, 1, LeafEvaluation::Heuristic(evaluator));
+        // a beam wide enough to keep every placement searches both known pieces exhaustively, like expectimax
+        // without a chance node
+        let wide = BeamSearch::new(1000, 2, LeafEvaluation::Heuristic(evaluator));
+        let exhaustive = Expectimax::new(2, 1000, LeafEvaluation::Heuristic(evaluator));
+        for seed in 0..2 {
+            let mut game = Game::with_seed(seed);
+            for _ in 0..5 {
+                assert_eq!(greedy.select_action(&game), evaluator.select_action(&game));
+                let action = wide.select_action(&game).unwrap();
+                assert_eq!(Some(action.clone()), exhaustive.select_action(&game));
+                if let StepResult::GameOver = game.place(&action) {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+
+use crate::game;
+
+use game::*;
+
+// Export of board positions as SVG images, e.g. for documentation and bug reports
+const CELL_SIZE : usize = 20;
+const MARGIN : usize = 10;
+const PREVIEW_CELL_SIZE : usize = 12;
+const PREVIEW_HEIGHT : usize = 3 * PREVIEW_CELL_SIZE; // two rows of blocks and a gap
+const TEXT_SIZE : usize = 14;
+const BACKGROUND : &str = "#202020";
+const GRID_COLOR : &str = "#404040";
+const TEXT_COLOR : &str = "#ffffff";
+const GHOST_OPACITY : f32 = 0.3;
+
+fn svg_color(color: [f32; 4]) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("rgb({},{},{})", channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+fn rect(x: usize, y: usize, width: usize, height: usize, fill: &str, opacity: f32) -> String {
+    if opacity < 1.0 {
+        format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>\n", x, y, width, height, fill, opacity)
+    } else {
+        format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", x, y, width, height, fill)
+    }
+}
+
+// SVG image of the board, optionally with the active piece, its ghost (where it would land with a hard drop) and the
+// queue of next pieces. The visible rows are drawn, and the rows above them if they contain blocks
+pub fn board_to_svg(board: &Board, active_piece: Option<Piece>, show_ghost: bool, next_pieces: &[Piece]) -> String {
+    let mut rows = RENDER_HEIGHT;
+    for y in RENDER_HEIGHT..GAME_HEIGHT {
+        if board.state[y].iter().any(|cell| *cell != 0) {
+            rows = y + 1;
+        }
+    }
+    if let Some(piece) = active_piece {
+        for (_, y) in piece.get_cells().iter() {
+            if *y >= rows as i32 && *y < GAME_HEIGHT as i32 {
+                rows = *y as usize + 1;
+            }
+        }
+    }
+
+    let board_width = GAME_WIDTH * CELL_SIZE;
+    let board_height = rows * CELL_SIZE;
+    let panel_width = if next_pieces.is_empty() { 0 } else { 4 * PREVIEW_CELL_SIZE + MARGIN };
+    let width = 2 * MARGIN + board_width + panel_width;
+    let height = (2 * MARGIN + board_height).max(2 * MARGIN + TEXT_SIZE + next_pieces.len() * PREVIEW_HEIGHT);
+
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n", width, height, width, height);
+    svg += &rect(0, 0, width, height, BACKGROUND, 1.0);
+    // top left corner of cell (x, y), y from bottom to top
+    let cell_position = |x: usize, y: usize| (MARGIN + x * CELL_SIZE, MARGIN + (rows - 1 - y) * CELL_SIZE);
+    for y in 0..rows {
+        for x in 0..GAME_WIDTH {
+            let (left, top) = cell_position(x, y);
+            svg += &format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"{}\"/>\n", left, top, CELL_SIZE, CELL_SIZE, GRID_COLOR);
+        }
+    }
+
+    let mut cells = board.state;
+    if let Some(piece) = active_piece {
+        if show_ghost {
+            for (x, y) in board.hard_drop(piece).get_cells().iter() {
+                if *x >= 0 && (*x as usize) < GAME_WIDTH && *y >= 0 && (*y as usize) < rows {
+                    let (left, top) = cell_position(*x as usize, *y as usize);
+                    svg += &rect(left, top, CELL_SIZE, CELL_SIZE, &svg_color(piece.color), GHOST_OPACITY);
+                }
+            }
+        }
+        for (x, y) in piece.get_cells().iter() {
+            if *x >= 0 && (*x as usize) < GAME_WIDTH && *y >= 0 && (*y as usize) < GAME_HEIGHT {
+                cells[*y as usize][*x as usize] = piece.get_type();
+            }
+        }
+    }
+    for (y, row) in cells.iter().take(rows).enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if *cell != 0 {
+                let (left, top) = cell_position(x, y);
+                svg += &rect(left, top, CELL_SIZE, CELL_SIZE, &svg_color(get_cell_color(*cell)), 1.0);
+            }
+        }
+    }
+
+    if !next_pieces.is_empty() {
+        let left = 2 * MARGIN + board_width;
+        svg += &format!("<text x=\"{}\" y=\"{}\" font-family=\"monospace\" font-size=\"{}\" fill=\"{}\">NEXT</text>\n", left, MARGIN + TEXT_SIZE, TEXT_SIZE, TEXT_COLOR);
+        for (k, piece) in next_pieces.iter().enumerate() {
+            let mut top = MARGIN + TEXT_SIZE + PREVIEW_CELL_SIZE / 2 + k * PREVIEW_HEIGHT;
+            for j in 0..4 {
+                if piece.shape[0][j].iter().all(|cell| *cell == 0) {
+                    continue;
+                }
+                for i in 0..4 {
+                    if piece.shape[0][j][i] != 0 {
+                        svg += &rect(left + i * PREVIEW_CELL_SIZE, top, PREVIEW_CELL_SIZE, PREVIEW_CELL_SIZE, &svg_color(piece.color), 1.0);
+                    }
+                }
+                top += PREVIEW_CELL_SIZE;
+            }
+        }
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+// SVG image of the current position of the game: board, active piece with its ghost and the next piece
+pub fn game_to_svg(game: &Game) -> String {
+    board_to_svg(&game.state, Some(game.active_piece), true, &[game.next_piece])
+}
+
+pub fn save_svg(path: &str, svg: &str) -> io::Result<()> {
+    fs::write(path, svg)
+}
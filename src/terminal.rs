@@ -0,0 +1,189 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::{cursor, event, execute, terminal};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::game;
+
+use game::*;
+
+// Rendering of a game with ANSI escape sequences and keyboard input in raw mode, to play or watch in a terminal (e.g. over SSH)
+const RESET : &str = "\x1b[0m";
+const CLEAR_LINE : &str = "\x1b[K";
+const BLOCK : &str = "██"; // a cell is two characters wide, so that it is roughly square
+const GHOST : &str = "░░";
+const EMPTY : &str = " .";
+const BORDER_COLOR : [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+// escape sequence setting the foreground to the (24 bit) colour
+fn color_code(color: [f32; 4]) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("\x1b[38;2;{};{};{}m", channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+fn colored(text: &str, color: [f32; 4]) -> String {
+    format!("{}{}{}", color_code(color), text, RESET)
+}
+
+// rows of the piece in its spawn rotation which contain blocks, from top to bottom
+fn preview_lines(piece: Option<&Piece>) -> [String; 2] {
+    let mut lines = [String::new(), String::new()];
+    let piece = match piece {
+        Some(piece) => piece,
+        None => return [" ".repeat(8), " ".repeat(8)]
+    };
+    let mut line = 0;
+    for j in 0..4 {
+        if line == lines.len() || piece.shape[0][j].iter().all(|cell| *cell == 0) {
+            continue;
+        }
+        for i in 0..4 {
+            if piece.shape[0][j][i] != 0 {
+                lines[line] += &colored(BLOCK, piece.color);
+            } else {
+                lines[line] += "  ";
+            }
+        }
+        line += 1;
+    }
+    for empty_line in lines.iter_mut().skip(line) {
+        *empty_line = " ".repeat(8);
+    }
+    lines
+}
+
+// Lines of the frame: the board with the active piece and its ghost, next to it the next and held piece,
+// the statistics of the game and the status (e.g. "PAUSED")
+pub fn render_game(game: &Game, status: &str) -> Vec<String> {
+    let board = game.state.simulate_board(game.active_piece);
+    let ghost = game.get_ghost_piece().get_cells();
+
+    let next = preview_lines(Some(&game.next_piece));
+    let hold = preview_lines(game.hold_piece.as_ref());
+    let mut counts = String::new();
+    for i in 0..PIECE_NAMES.len() {
+        counts += &format!("{}{} ", colored(PIECE_NAMES[i], PIECE_COLORS[i]), game.piece_counts[i]);
+    }
+    let panel = [
+        "NEXT      HOLD".to_string(),
+        format!("{}  {}", next[0], hold[0]),
+        format!("{}  {}", next[1], hold[1]),
+        String::new(),
+        format!("Score   {}", game.score),
+        format!("Level   {}", game.get_level()),
+        format!("Lines   {}", game.lines_cleared),
+        format!("Pieces  {}", game.pieces_placed),
+        format!("PPS     {:.2}", game.get_pieces_per_second()),
+        counts,
+        String::new(),
+        status.to_string()
+    ];
+
+    let mut lines = Vec::new();
+    for y in (0..RENDER_HEIGHT).rev() {
+        let mut line = colored("│", BORDER_COLOR);
+        for x in 0..RENDER_WIDTH {
+            let cell = board.state[y][x];
+            if cell != 0 {
+                line += &colored(BLOCK, get_cell_color(cell));
+            } else if ghost.contains(&(x as i32, y as i32)) {
+                line += &colored(GHOST, game.active_piece.color);
+            } else {
+                line += EMPTY;
+            }
+        }
+        line += &colored("│", BORDER_COLOR);
+        let row = RENDER_HEIGHT - 1 - y;
+        if row < panel.len() {
+            line += "  ";
+            line += &panel[row];
+        }
+        lines.push(line);
+    }
+    lines.push(colored(&format!("└{}┘", "─".repeat(2 * RENDER_WIDTH)), BORDER_COLOR));
+    lines
+}
+
+// name of the key as used by the key bindings (the names of the piston keys), None for keys without a binding name
+pub fn key_name(code: KeyCode) -> Option<String> {
+    match code {
+        KeyCode::Left => Some("Left".to_string()),
+        KeyCode::Right => Some("Right".to_string()),
+        KeyCode::Up => Some("Up".to_string()),
+        KeyCode::Down => Some("Down".to_string()),
+        KeyCode::Enter => Some("Return".to_string()),
+        KeyCode::Tab => Some("Tab".to_string()),
+        KeyCode::Backspace => Some("Backspace".to_string()),
+        KeyCode::Char(' ') => Some("Space".to_string()),
+        KeyCode::Char(character) if character.is_ascii_digit() => Some(format!("D{}", character)),
+        KeyCode::Char(character) if character.is_ascii_alphabetic() => Some(character.to_ascii_uppercase().to_string()),
+        _ => None
+    }
+}
+
+// Keyboard input of the terminal
+pub enum TerminalInput {
+    Key(String), // name of a pressed key, see key_name
+    Quit // escape or ctrl-c, which no longer interrupts the program in raw mode
+}
+
+// Terminal in raw mode showing the alternate screen, restored when dropped
+pub struct Terminal {
+    previous: Vec<String> // lines currently shown, only changed lines are written again
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+        Ok(Terminal {
+            previous: Vec::new()
+        })
+    }
+
+    pub fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        let mut output = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            if self.previous.get(i) != Some(line) {
+                // move the cursor to the start of the line (1-based)
+                output += &format!("\x1b[{};1H{}{}", i + 1, line, CLEAR_LINE);
+            }
+        }
+        if output.is_empty() {
+            return Ok(());
+        }
+        self.previous = lines.to_vec();
+        let mut stdout = io::stdout();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()
+    }
+
+    // Wait up to the timeout for a pressed key, key repeats of the terminal count as presses
+    pub fn read_input(&mut self, timeout: Duration) -> io::Result<Option<TerminalInput>> {
+        if !event::poll(timeout)? {
+            return Ok(None);
+        }
+        match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => {
+                if key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+                    return Ok(Some(TerminalInput::Quit));
+                }
+                Ok(key_name(key.code).map(TerminalInput::Key))
+            },
+            Event::Resize(_, _) => {
+                // the screen may have been cleared, so draw every line again
+                self.previous.clear();
+                Ok(None)
+            },
+            _ => Ok(None)
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,67 @@
+// The game runs at a fixed number of frames per second, Game::tick advances it by a single frame
+pub const FRAMES_PER_SECOND : f64 = 60.0;
+pub const FRAME_DURATION : f64 = 1.0 / FRAMES_PER_SECOND;
+
+// gravity at which the piece falls to the ground within a single frame
+pub const GRAVITY_20G : f64 = 20.0;
+// from this level on, pieces always fall with 20G
+const LEVEL_20G : i32 = 20;
+
+// Timing rules shared by the human, AI and headless modes
+#[derive(Clone, Copy)]
+pub struct TimingConfig {
+    pub lock_delay: u32, // frames a piece may rest on the ground before it is locked
+    pub move_reset_limit: u32, // moves and rotations on the ground which restart the lock delay
+    pub soft_drop_factor: f64, // gravity is multiplied with this factor while soft drop is held
+    pub gravity_override: Option<f64> // fixed gravity in rows per frame instead of the curve by level, e.g. GRAVITY_20G
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimingConfig {
+    pub fn new() -> TimingConfig {
+        TimingConfig {
+            lock_delay: 30,
+            move_reset_limit: 15,
+            soft_drop_factor: 20.0,
+            gravity_override: None
+        }
+    }
+
+    // Guideline gravity curve: (0.8 - (level - 1) * 0.007)^(level - 1) seconds per row, converted to rows per frame
+    pub fn gravity(&self, level: i32) -> f64 {
+        if let Some(gravity) = self.gravity_override {
+            return gravity;
+        }
+        if level >= LEVEL_20G {
+            return GRAVITY_20G;
+        }
+        let level = level.max(1) as f64;
+        let seconds_per_row = (0.8 - (level - 1.0) * 0.007).powf(level - 1.0);
+        (1.0 / (seconds_per_row * FRAMES_PER_SECOND)).min(GRAVITY_20G)
+    }
+}
+
+// Lock delay and gravity state of the active piece
+#[derive(Clone, Copy)]
+pub struct PieceTimer {
+    pub gravity_progress: f64, // fraction of a row the piece has fallen since its last move down
+    pub lock_frames: u32, // frames spent on the ground since the lock delay was (re)started
+    pub move_resets: u32,
+    pub lowest_row: i32 // the move resets are restored once the piece falls below its lowest row so far
+}
+
+impl PieceTimer {
+    pub fn new(row: i32) -> PieceTimer {
+        PieceTimer {
+            gravity_progress: 0.0,
+            lock_frames: 0,
+            move_resets: 0,
+            lowest_row: row
+        }
+    }
+}